@@ -0,0 +1,75 @@
+use crate::ray::Ray;
+use crate::vector3::{Point3, Vector3};
+
+// 轴对齐包围盒
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// 包含两个点的最小包围盒，两个点不需要有大小顺序
+    pub fn from_points(a: Point3, b: Point3) -> Aabb {
+        Aabb {
+            min: Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// 同时包含两个包围盒的最小包围盒
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    // 最长的轴，0、1、2 分别表示 x、y、z
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x > size.y && size.x > size.z {
+            0
+        } else if size.y > size.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // 板块法（slab）判断射线在 [min, max] 之间是否穿过包围盒
+    pub fn hit(&self, ray: &Ray, mut min: f64, mut max: f64) -> bool {
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            // 写成这样是为了在 t 为 NaN 的时候保留原来的范围
+            min = if t0 > min { t0 } else { min };
+            max = if t1 < max { t1 } else { max };
+            if max < min {
+                return false;
+            }
+        }
+        true
+    }
+}
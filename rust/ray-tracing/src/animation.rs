@@ -0,0 +1,205 @@
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+use crate::scene::CameraSettings;
+use crate::vector3::Point3;
+
+// 关键帧之间的插值方法
+#[derive(Clone, Copy, Default)]
+pub enum Interpolation {
+    Linear,
+    // 经过所有关键帧的三次样条，速度在关键帧处连续，镜头运动更平滑
+    #[default]
+    CatmullRom,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "catmull-rom" => Ok(Interpolation::CatmullRom),
+            _ => Err(format!("unknown interpolation: {s}")),
+        }
+    }
+}
+
+// 某一帧的相机参数
+pub struct Keyframe {
+    pub frame: f64,
+    pub origin: Point3,
+    pub at: Point3,
+    pub fov: f64,
+    pub focus: f64,
+    pub aperture: f64,
+}
+
+// 相机动画，关键帧按照帧号排序，渲染 start 到 end（包含）的每一帧
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Animation {
+    /// 关键帧不能为空，帧号不能重复
+    pub fn new(mut keyframes: Vec<Keyframe>, interpolation: Interpolation) -> Animation {
+        assert!(!keyframes.is_empty(), "动画至少需要一个关键帧");
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        let start = keyframes[0].frame.max(0.0).round() as u64;
+        let end = keyframes[keyframes.len() - 1].frame.max(0.0).round() as u64;
+        Animation {
+            keyframes,
+            interpolation,
+            start,
+            end,
+        }
+    }
+
+    /// 第 frame 帧的相机，vup 和快门使用 base 的设置。
+    /// 第一个关键帧之前和最后一个关键帧之后相机保持不动
+    pub fn camera(&self, base: &CameraSettings, frame: f64) -> CameraSettings {
+        CameraSettings {
+            origin: self.interpolate(frame, |key| key.origin),
+            at: self.interpolate(frame, |key| key.at),
+            vup: base.vup,
+            fov: self.interpolate(frame, |key| key.fov),
+            aperture: self.interpolate(frame, |key| key.aperture).max(0.0),
+            focus: self.interpolate(frame, |key| key.focus),
+            shutter: base.shutter,
+        }
+    }
+
+    // 位置和标量使用同样的插值
+    fn interpolate<T>(&self, frame: f64, value: impl Fn(&Keyframe) -> T) -> T
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    {
+        let keys = &self.keyframes;
+        let last = keys.len() - 1;
+        // frame 所在的区间 [keys[i], keys[i + 1]]
+        let i = keys.partition_point(|key| key.frame <= frame);
+        if i == 0 {
+            return value(&keys[0]);
+        }
+        if i > last {
+            return value(&keys[last]);
+        }
+        let (i, j) = (i - 1, i);
+        let (a, b) = (&keys[i], &keys[j]);
+        let length = b.frame - a.frame;
+        let t = (frame - a.frame) / length;
+        let (p1, p2) = (value(a), value(b));
+
+        match self.interpolation {
+            Interpolation::Linear => p1 + (p2 - p1) * t,
+            Interpolation::CatmullRom => {
+                // 每个关键帧的切线是前后两个关键帧的差除以帧数（两端用单侧的差），
+                // 关键帧间隔不均匀的时候速度也是连续的，再换算成这个区间上的参数
+                let tangent = |k: usize| {
+                    let (before, after) = (k.saturating_sub(1), (k + 1).min(last));
+                    let frames = keys[after].frame - keys[before].frame;
+                    (value(&keys[after]) - value(&keys[before])) * (length / frames)
+                };
+                let (m1, m2) = (tangent(i), tangent(j));
+                // 三次 Hermite 基函数
+                let (t2, t3) = (t * t, t * t * t);
+                p1 * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + m1 * (t3 - 2.0 * t2 + t)
+                    + p2 * (-2.0 * t3 + 3.0 * t2)
+                    + m2 * (t3 - t2)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::scene;
+    use crate::vector3::Vector3;
+
+    fn keyframe(frame: f64, x: f64) -> Keyframe {
+        Keyframe {
+            frame,
+            origin: Point3::new(x, 0.0, 0.0),
+            at: Point3::new(0.0, 0.0, -1.0),
+            fov: 40.0 + x,
+            focus: 1.0,
+            aperture: 0.0,
+        }
+    }
+
+    fn base() -> CameraSettings {
+        CameraSettings {
+            origin: Point3::default(),
+            at: Point3::new(0.0, 0.0, -1.0),
+            vup: Vector3::new(0.0, 1.0, 0.0),
+            fov: 40.0,
+            aperture: 0.0,
+            focus: 1.0,
+            shutter: (0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn interpolation_passes_through_keyframes() {
+        let keys = || vec![keyframe(10.0, 4.0), keyframe(1.0, 0.0), keyframe(4.0, 1.0)];
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            let animation = Animation::new(keys(), interpolation);
+            assert_eq!((animation.start, animation.end), (1, 10));
+            for (frame, x) in [(1.0, 0.0), (4.0, 1.0), (10.0, 4.0)] {
+                let camera = animation.camera(&base(), frame);
+                assert!((camera.origin.x - x).abs() < 1e-12);
+                assert!((camera.fov - 40.0 - x).abs() < 1e-12);
+            }
+            // 范围之外保持不动
+            assert_eq!(animation.camera(&base(), -5.0).origin.x, 0.0);
+            assert_eq!(animation.camera(&base(), 20.0).origin.x, 4.0);
+        }
+
+        let linear = Animation::new(keys(), Interpolation::Linear);
+        assert!((linear.camera(&base(), 7.0).origin.x - 2.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn catmull_rom_is_smooth() {
+        // 匀速运动的关键帧间隔不均匀，样条仍然是匀速的
+        let keys = vec![keyframe(0.0, 0.0), keyframe(3.0, 3.0), keyframe(10.0, 10.0)];
+        let animation = Animation::new(keys, Interpolation::CatmullRom);
+        for frame in 0..=10 {
+            let x = animation.camera(&base(), frame as f64).origin.x;
+            assert!((x - frame as f64).abs() < 1e-9, "{frame}: {x}");
+        }
+
+        // 关键帧两边的速度相同
+        let keys = vec![keyframe(0.0, 0.0), keyframe(2.0, 1.0), keyframe(6.0, 5.0)];
+        let animation = Animation::new(keys, Interpolation::CatmullRom);
+        let x = |frame: f64| animation.camera(&base(), frame).origin.x;
+        let h = 1e-5;
+        let (left, right) = ((x(2.0) - x(2.0 - h)) / h, (x(2.0 + h) - x(2.0)) / h);
+        assert!((left - right).abs() < 1e-3, "{left} vs {right}");
+    }
+
+    #[test]
+    fn scene_keyframes_orbit_the_camera() {
+        let text = include_str!("../scenes/turntable.toml");
+        let scene = scene::parse(text, Path::new("")).unwrap();
+        let animation = scene.animation.unwrap();
+        assert_eq!((animation.start, animation.end), (1, 120));
+        // 第 1 帧和第 121 帧都在起点，中间的每一帧离转轴的距离都差不多
+        let camera = |frame| animation.camera(&scene.camera, frame);
+        assert!((camera(1.0).origin - camera(121.0).origin).length() < 0.01);
+        for frame in 1..=120 {
+            let camera = camera(frame as f64);
+            let offset = camera.origin - camera.at;
+            let radius = Vector3::new(offset.x, 0.0, offset.z).length();
+            assert!((radius - 13.34).abs() < 0.2, "{frame}: {radius}");
+            assert!((camera.focus - offset.length()).abs() < 0.2);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::ops::AddAssign;
+use std::str::FromStr;
+
+use crate::color::Display;
+use crate::film::Film;
+use crate::hdr::HdrImage;
+use crate::hittable::HitRecord;
+use crate::image::Image;
+use crate::material::Kind;
+use crate::ray::Ray;
+use crate::render::Settings;
+use crate::sampler;
+use crate::vector3::{Color, Vector3};
+
+// 辅助图像（arbitrary output variable），记录相机射线第一次命中的信息
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pass {
+    // 世界坐标系里的法线
+    Normal,
+    // 交点到相机镜头的距离
+    Depth,
+    Albedo,
+    Material,
+    // 场景里的第几个物体
+    Object,
+}
+
+impl Pass {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Normal => "normal",
+            Pass::Depth => "depth",
+            Pass::Albedo => "albedo",
+            Pass::Material => "material",
+            Pass::Object => "object",
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Pass::Normal),
+            "depth" => Ok(Pass::Depth),
+            "albedo" => Ok(Pass::Albedo),
+            "material" => Ok(Pass::Material),
+            "object" => Ok(Pass::Object),
+            _ => Err(format!("unknown pass: {s}")),
+        }
+    }
+}
+
+// 一个像素的辅助信息，没有命中的像素深度是无穷大
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    pub normal: Vector3,
+    pub depth: f64,
+    pub albedo: Color,
+    pub material: Option<Kind>,
+    pub object: usize,
+}
+
+const EMPTY: Pixel = Pixel {
+    normal: Vector3::new(0.0, 0.0, 0.0),
+    depth: f64::INFINITY,
+    albedo: Color::new(0.0, 0.0, 0.0),
+    material: None,
+    object: 0,
+};
+
+// 也是降噪使用的特征缓冲区
+pub struct Buffers {
+    pub width: u64,
+    pub height: u64,
+    pub pixels: Vec<Pixel>,
+}
+
+// 一个像素的所有采样累加的第一次命中的信息，和颜色使用同样的相机射线，
+// 渲染的时候和颜色一起累加到 Film 里
+#[derive(Clone, Copy, Default)]
+pub struct Sum {
+    pub normal: Vector3,
+    // 交点到射线起点（镜头上的点）的距离之和。相机射线的方向没有归一化，
+    // 所以不是射线的参数 t，而是 t 乘以方向的长度
+    pub depth: f64,
+    // 命中物体的采样数，法线和深度按照它取平均
+    pub hits: u64,
+    // 没有命中的采样加上背景的颜色
+    pub albedo: Color,
+    // 序号最小的命中物体的采样的材质和物体编号
+    pub material: Option<Kind>,
+    pub object: usize,
+}
+
+impl Sum {
+    /// 累加一次采样的相机射线，record 是它第一次命中的结果
+    pub fn add_sample(&mut self, ray: &Ray, record: Option<&HitRecord>, settings: &Settings) {
+        let Some(record) = record else {
+            self.albedo += settings.background.color(ray);
+            return;
+        };
+        if self.hits == 0 {
+            self.material = Some(record.material.kind());
+            self.object = record.object;
+        }
+        self.hits += 1;
+        self.normal += record.normal;
+        self.depth += record.t * ray.direction.length();
+        self.albedo += record.material.albedo(record);
+    }
+}
+
+impl AddAssign for Sum {
+    // rhs 是序号更大的采样
+    fn add_assign(&mut self, rhs: Self) {
+        if self.hits == 0 {
+            self.material = rhs.material;
+            self.object = rhs.object;
+        }
+        self.normal += rhs.normal;
+        self.depth += rhs.depth;
+        self.hits += rhs.hits;
+        self.albedo += rhs.albedo;
+    }
+}
+
+impl Buffers {
+    /// 从 Film 里累加的信息计算每个像素的平均值，Film 没有记录辅助信息的时候返回 None
+    pub fn new(film: &Film) -> Option<Buffers> {
+        let features = film.features.as_ref()?;
+        let pixels = features
+            .iter()
+            .zip(&film.counts)
+            .map(|(sum, &count)| match sum.hits {
+                0 => Pixel {
+                    albedo: sum.albedo / count.max(1) as f64,
+                    ..EMPTY
+                },
+                hits => Pixel {
+                    normal: match sum.normal.length() > 0.0 {
+                        true => sum.normal.unit(),
+                        false => sum.normal,
+                    },
+                    depth: sum.depth / hits as f64,
+                    albedo: sum.albedo / count as f64,
+                    material: sum.material,
+                    object: sum.object,
+                },
+            })
+            .collect();
+        Some(Buffers {
+            width: film.width,
+            height: film.height,
+            pixels,
+        })
+    }
+
+    /// 把一种辅助信息转换成便于查看的图片
+    pub fn image(&self, pass: Pass) -> Image {
+        // 深度图里最远的交点是黑色，最近的是白色
+        let farthest = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel.depth)
+            .filter(|depth| depth.is_finite())
+            .fold(0.0, f64::max);
+
+        let byte = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let data = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| {
+                let hit = pixel.depth.is_finite();
+                match pass {
+                    Pass::Normal if hit => {
+                        let n = pixel.normal;
+                        [n.x, n.y, n.z].map(|value| byte(0.5 * (value + 1.0)))
+                    }
+                    Pass::Depth if hit && farthest > 0.0 => [byte(1.0 - pixel.depth / farthest); 3],
+                    Pass::Albedo => Display::default().encode(pixel.albedo),
+                    Pass::Material => match pixel.material {
+                        Some(kind) => palette(kind),
+                        None => [0; 3],
+                    },
+                    Pass::Object if pixel.object > 0 => {
+                        // 每个物体一种随机的颜色
+                        let hash = sampler::mix(pixel.object as u64).to_le_bytes();
+                        [hash[0], hash[1], hash[2]].map(|value| value / 2 + 64)
+                    }
+                    _ => [0; 3],
+                }
+            })
+            .collect();
+
+        Image {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// 高动态范围格式保存原始的浮点数据：法线的分量、到镜头的距离、线性的反照率，
+    /// 材质和物体保存编号（材质从 1 开始）。没有命中的像素是 0，反照率是背景的颜色
+    pub fn hdr(&self, pass: Pass) -> HdrImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|pixel| {
+                let hit = pixel.depth.is_finite();
+                let gray = |value: f64| Color::new(value, value, value);
+                match pass {
+                    Pass::Normal if hit => pixel.normal,
+                    Pass::Depth if hit => gray(pixel.depth),
+                    Pass::Albedo => pixel.albedo,
+                    Pass::Material => {
+                        gray(pixel.material.map_or(0.0, |kind| kind as u8 as f64 + 1.0))
+                    }
+                    Pass::Object => gray(pixel.object as f64),
+                    _ => Color::default(),
+                }
+            })
+            .collect();
+        HdrImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+}
+
+fn palette(kind: Kind) -> [u8; 3] {
+    match kind {
+        Kind::Lambert => [200, 200, 200],
+        Kind::Metal => [80, 120, 220],
+        Kind::Dielectric => [80, 220, 220],
+        Kind::DiffuseLight => [255, 220, 60],
+        Kind::Isotropic => [200, 90, 200],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable::Lights;
+    use crate::render::{self, Settings};
+    use crate::{bvh, scene};
+
+    // 半径 2 的球，相机在 5 的地方，对焦距离和到球面的距离不同
+    const SCENE: &str = r#"
+[camera]
+origin = [0, 0, 5]
+at = [0, 0, 0]
+fov = 60
+aperture = 0
+focus = 2
+
+[materials.paint]
+type = "Lambert"
+albedo = [0.2, 0.4, 0.6]
+
+[[objects]]
+type = "Sphere"
+center = [0, 0, 0]
+radius = 2
+material = "paint"
+"#;
+
+    // 依次渲染到 samples 里的每个采样数，返回累加了辅助信息的 Film
+    fn render_with(samples: &[u64]) -> Film {
+        let scene = scene::parse(SCENE, Path::new("")).unwrap();
+        let camera = scene.camera.camera(1.0);
+        let world = bvh::accelerate(scene.world);
+        let mut film = Film::new(15, 15, 3).with_features();
+        for &samples in samples {
+            let settings = Settings {
+                width: 15,
+                height: 15,
+                samples,
+                max_depth: 4,
+                background: Background::black(),
+                lights: Lights::new(),
+                environment: None,
+                threads: 1,
+                progress: false,
+                checkpoint: None,
+                adaptive: None,
+            };
+            render::render(&world, &camera, &settings, &mut film);
+        }
+        film
+    }
+
+    #[test]
+    fn features_come_from_camera_samples() {
+        let film = render_with(&[8]);
+        let buffers = Buffers::new(&film).unwrap();
+        assert!(Buffers::new(&Film::new(15, 15, 3)).is_none());
+
+        // 深度是到镜头的距离，不是射线的参数 t（对焦距离是 2，t 大约是 1.5）
+        let center = &buffers.pixels[7 * 15 + 7];
+        assert!((center.depth - 3.0).abs() < 0.01, "{}", center.depth);
+        assert!(center.normal.z > 0.99);
+        assert!((center.albedo - Color::new(0.2, 0.4, 0.6)).length() < 1e-9);
+        assert!(matches!(center.material, Some(Kind::Lambert)));
+        // 角上看不到球
+        assert!(buffers.pixels[0].depth.is_infinite());
+        assert!(buffers.pixels[0].material.is_none());
+
+        // 高动态范围的输出是原始的数据，不经过 8 位的转换
+        let depth = buffers.hdr(Pass::Depth);
+        assert_eq!(depth.pixels[7 * 15 + 7].x, center.depth);
+        assert_eq!(depth.pixels[0].x, 0.0);
+        assert_eq!(
+            buffers.hdr(Pass::Normal).pixels[7 * 15 + 7].z,
+            center.normal.z
+        );
+        assert_eq!(buffers.hdr(Pass::Material).pixels[7 * 15 + 7].x, 1.0);
+        assert_eq!(
+            buffers.hdr(Pass::Albedo).pixels[7 * 15 + 7].y,
+            center.albedo.y
+        );
+
+        // 每次采样都记录了一次相机射线
+        let sums = film.features.as_ref().unwrap();
+        for (sum, &count) in sums.iter().zip(&film.counts) {
+            assert_eq!(count, 8);
+            assert!(sum.hits <= count);
+        }
+        assert!(sums.iter().any(|sum| (1..8).contains(&sum.hits)));
+    }
+
+    #[test]
+    fn features_do_not_depend_on_rounds() {
+        let (once, twice) = (render_with(&[8]), render_with(&[3, 8]));
+        let (a, b) = (once.features.unwrap(), twice.features.unwrap());
+        // 分轮累加的时候浮点数相加的顺序不同，只有舍入误差
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!((a.hits, a.object), (b.hits, b.object));
+            assert_eq!(a.material.map(|k| k as u8), b.material.map(|k| k as u8));
+            assert!((a.depth - b.depth).abs() < 1e-9);
+            assert!((a.normal - b.normal).length() < 1e-9);
+            assert!((a.albedo - b.albedo).length() < 1e-9);
+        }
+    }
+}
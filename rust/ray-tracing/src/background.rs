@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use crate::environment::Environment;
+use crate::ray::Ray;
+use crate::vector3::Color;
+
+// 默认的天空从白色渐变到蓝色
+pub const SKY_BOTTOM: Color = Color {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+};
+pub const SKY_TOP: Color = Color {
+    x: 0.5,
+    y: 0.7,
+    z: 1.0,
+};
+
+// 射线没有命中任何物体时看到的颜色
+#[derive(Clone)]
+pub enum Background {
+    // 从下到上的渐变色
+    Gradient { bottom: Color, top: Color },
+    Solid(Color),
+    // HDR 环境贴图
+    Environment(Arc<Environment>),
+}
+
+impl Background {
+    pub fn black() -> Background {
+        Background::Solid(Color::default())
+    }
+
+    /// 环境贴图可以作为光源采样
+    pub fn environment(&self) -> Option<&Arc<Environment>> {
+        match self {
+            Background::Environment(environment) => Some(environment),
+            _ => None,
+        }
+    }
+
+    pub fn color(&self, ray: &Ray) -> Color {
+        match *self {
+            Background::Gradient { bottom, top } => {
+                // 射线未命中，射线的单位向量
+                let unit = ray.direction().unit();
+                // 因为需要得到上下渐变的背景图，所以需要对 y 进行插值。
+                let t = 0.5 * (unit.y + 1.0);
+                // 线性插值，根据不同的光线得到在下面这个范围里的不同的颜色，并且是渐变色。
+                (1.0 - t) * bottom + t * top
+            }
+            Background::Solid(color) => color,
+            Background::Environment(ref environment) => environment.color(ray.direction),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: SKY_BOTTOM,
+            top: SKY_TOP,
+        }
+    }
+}
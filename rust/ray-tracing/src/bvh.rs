@@ -0,0 +1,155 @@
+use std::cmp::Ordering;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord, World};
+use crate::ray::Ray;
+use crate::stats;
+
+// 层次包围盒，每次按照包围盒中心最分散的轴排序，从中间分成两半
+pub struct BvhNode {
+    bbox: Aabb,
+    left: Box<dyn Hit>,
+    // 只剩一个物体的时候没有右节点
+    right: Option<Box<dyn Hit>>,
+}
+
+impl BvhNode {
+    /// 物体不能为空，并且每个物体都必须有包围盒
+    pub fn new(world: World) -> BvhNode {
+        let objects = world
+            .into_iter()
+            .map(|object| {
+                let bbox = object.bounding_box().expect("BVH 中的物体必须有包围盒");
+                (bbox, object)
+            })
+            .collect();
+
+        Self::build(objects)
+    }
+
+    fn build(mut objects: Vec<(Aabb, Box<dyn Hit>)>) -> BvhNode {
+        assert!(!objects.is_empty(), "不能使用空的场景构建 BVH");
+
+        if objects.len() == 1 {
+            let (bbox, left) = objects.pop().unwrap();
+            return BvhNode {
+                bbox,
+                left,
+                right: None,
+            };
+        }
+
+        let centers = objects
+            .iter()
+            .map(|(bbox, _)| Aabb::from_points(bbox.centroid(), bbox.centroid()))
+            .reduce(Aabb::union)
+            .unwrap();
+        let axis = centers.longest_axis();
+        objects.sort_by(|(a, _), (b, _)| {
+            let (a, b) = (a.centroid()[axis], b.centroid()[axis]);
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+
+        let rights = objects.split_off(objects.len() / 2);
+        let left = Self::node(objects);
+        let right = Self::node(rights);
+
+        BvhNode {
+            bbox: left.0.union(right.0),
+            left: left.1,
+            right: Some(right.1),
+        }
+    }
+
+    // 只有一个物体的时候不再包一层节点
+    fn node(mut objects: Vec<(Aabb, Box<dyn Hit>)>) -> (Aabb, Box<dyn Hit>) {
+        if objects.len() == 1 {
+            return objects.pop().unwrap();
+        }
+        let node = Self::build(objects);
+        (node.bbox, Box::new(node))
+    }
+}
+
+impl Hit for BvhNode {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, min, max) {
+            return None;
+        }
+
+        // 每个子节点的包围盒或者物体算一次求交测试
+        stats::count_tests(1 + self.right.is_some() as u64);
+        let left = self.left.hit(ray, min, max);
+        let nearest = left.as_ref().map_or(max, |record| record.t);
+        let right = self.right.as_ref().and_then(|r| r.hit(ray, min, nearest));
+
+        right.or(left)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+/// 把有包围盒的物体放进 BVH，没有包围盒的物体还是逐个测试
+pub fn accelerate(world: World) -> World {
+    let (bounded, mut unbounded): (World, World) = world
+        .into_iter()
+        .partition(|object| object.bounding_box().is_some());
+
+    if !bounded.is_empty() {
+        unbounded.push(Box::new(BvhNode::new(bounded)));
+    }
+    unbounded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::material::Lambert;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use crate::vector3::{Color, Point3, Vector3};
+
+    #[test]
+    fn bvh_matches_linear_traversal() {
+        let material = Arc::new(Lambert::new(Color::new(0.5, 0.5, 0.5)));
+
+        let spheres = || {
+            let mut world = World::new();
+            let mut sampler = Sampler::new(1);
+            for i in 0..500 {
+                let center = Point3::random(-10.0..10.0, &mut sampler);
+                let mut radius = sampler.gen_range(0.1..1.0);
+                // 半径是负数的球法线朝里，也必须能被 BVH 命中
+                if i % 10 == 0 {
+                    radius = -radius;
+                }
+                world.push(Box::new(Sphere::new(center, radius, material.clone())));
+            }
+            world
+        };
+        let linear = spheres();
+        let bvh = accelerate(spheres());
+
+        let mut sampler = Sampler::new(2);
+        for _ in 0..10000 {
+            let origin = Point3::random(-15.0..15.0, &mut sampler);
+            let ray = Ray::new(origin, Vector3::random_unit(&mut sampler), 0.0);
+
+            let expected = linear.hit(&ray, 0.001, f64::INFINITY);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    assert_eq!(expected.t, actual.t);
+                    assert_eq!(expected.point.x, actual.point.x);
+                    assert_eq!(expected.normal.y, actual.normal.y);
+                }
+                _ => panic!("BVH 和逐个遍历的结果不一致"),
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use super::ray::Ray;
+use super::sampler::Sampler;
 use super::vector3::{Point3, Vector3};
 
 pub struct Camera {
@@ -9,6 +10,8 @@ pub struct Camera {
     cu: Vector3,
     cv: Vector3,
     radius: f64,
+    // 快门打开和关闭的时刻
+    shutter: (f64, f64),
 }
 
 impl Camera {
@@ -42,17 +45,27 @@ impl Camera {
             cu,
             cv,
             radius: aperture / 2.0,
+            shutter: (0.0, 0.0),
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = self.radius * Vector3::random_in_unit_disk();
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn get_ray(&self, u: f64, v: f64, sampler: &mut Sampler) -> Ray {
+        let rd = self.radius * Vector3::random_in_unit_disk(sampler);
         let offset = self.cu * rd.x + self.cv * rd.y;
         let vector3 = self.corner + u * self.horizontal + v * self.vertical;
 
-        Ray {
-            origin: self.origin + offset,
-            direction: vector3 - self.origin - offset,
-        }
+        // 快门瞬间开合的时候不用消耗随机数，静止的场景渲染结果保持不变
+        let (open, close) = self.shutter;
+        let time = match close > open {
+            true => sampler.gen_range(open..close),
+            false => open,
+        };
+
+        Ray::new(self.origin + offset, vector3 - self.origin - offset, time)
     }
 }
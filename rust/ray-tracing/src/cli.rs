@@ -0,0 +1,343 @@
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::aov::Pass;
+use crate::color::{Display, ToneMap};
+use crate::hdr::Compression;
+use crate::image::Format;
+use crate::scene::ImageSettings;
+
+const USAGE: &str = "\
+Usage: game [OPTIONS] [SCENE]
+
+Arguments:
+  [SCENE]                 场景文件（TOML），默认是内置的随机场景
+
+Options:
+      --scene <NAME>      内置场景（random、cornell、cornell-smoke）或者场景文件 [random]
+  -w, --width <PIXELS>    图片的宽度，高度由宽高比决定
+  -r, --ratio <RATIO>     宽高比，比如 1.5 或者 16:9
+  -s, --samples <N>       每个像素的采样数，自适应采样时是最大采样数
+      --adaptive <ERROR>  自适应采样，像素的误差小于 ERROR（比如 0.01）之后停止采样
+      --min-samples <N>   自适应采样时每个像素至少的采样数，也是每一轮的采样数 [16]
+      --heatmap <PATH>    输出每个像素的采样数的热力图
+      --denoise           按照法线、深度和反照率对渲染结果降噪
+      --aov <PASSES>      额外输出的辅助图像，用逗号分隔：normal、depth、albedo、material、object，
+                          保存在输出文件旁边，比如 image-normal.png
+  -d, --max-depth <N>     光线的最大反射次数，反射三次之后按照俄罗斯轮盘赌随机结束路径
+      --exposure <STOPS>  曝光补偿，每加 1 亮度翻倍 [0]
+      --tone-map <NAME>   色调映射：clamp（默认）、reinhard、aces
+  -o, --output <PATH>     输出文件，默认输出到标准输出
+  -f, --format <FORMAT>   输出格式：ppm、png、pfm、exr，默认根据扩展名选择。
+                          pfm 和 exr 保存没有经过曝光和色调映射的线性颜色
+      --exr-compression <METHOD>
+                          EXR 的压缩方式：zip（默认）、none
+      --seed <N>          随机数种子，相同的种子渲染出相同的图片
+  -t, --threads <N>       渲染线程数，默认使用所有的核心
+      --no-light-sampling 不直接对光源采样，只按照材质采样（用来对比噪点）
+      --checkpoint <PATH> 定期把渲染进度保存到检查点文件
+      --checkpoint-minutes <M>
+                          保存检查点的间隔（分钟）[10]
+      --checkpoint-samples <N>
+                          每个像素每完成 N 次采样保存一次检查点
+      --resume <PATH>     从检查点文件继续渲染，直到达到目标采样数
+      --frames <START:END>
+                          场景里有相机动画时渲染的帧的范围，默认是场景里的设置
+      --resume-frames     跳过已经渲染好的帧，从第一个缺少的帧开始渲染
+  -q, --quiet             不输出任何进度信息和统计信息
+      --progress          输出渲染进度和结束时的统计信息（默认）
+      --stats-json <PATH> 把渲染的统计信息（射线数、求交次数、耗时等）保存成 JSON 文件
+  -h, --help              显示帮助信息
+";
+
+pub struct Options {
+    // 场景文件的路径，`random` 表示内置的随机场景
+    pub scene: String,
+    pub width: Option<u64>,
+    pub ratio: Option<f64>,
+    pub samples: Option<u64>,
+    // 自适应采样的误差阈值
+    pub adaptive: Option<f64>,
+    pub min_samples: u64,
+    pub heatmap: Option<String>,
+    pub denoise: bool,
+    pub aov: Vec<Pass>,
+    pub max_depth: Option<u64>,
+    pub exposure: Option<f64>,
+    pub tone_map: Option<ToneMap>,
+    pub output: Option<String>,
+    // 没有指定的时候根据输出文件的扩展名选择
+    pub format: Option<Format>,
+    pub exr_compression: Option<Compression>,
+    pub seed: Option<u64>,
+    pub threads: usize,
+    pub light_sampling: bool,
+    pub progress: bool,
+    pub stats_json: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Duration,
+    pub checkpoint_samples: Option<u64>,
+    pub resume: Option<String>,
+    pub frames: Option<(u64, u64)>,
+    pub resume_frames: bool,
+}
+
+impl Options {
+    /// 解析命令行参数，参数错误时输出用法并退出
+    pub fn parse() -> Options {
+        Self::parse_from(std::env::args().skip(1)).unwrap_or_else(|message| {
+            eprintln!("error: {message}\n\n{USAGE}");
+            process::exit(2);
+        })
+    }
+
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            scene: String::from("random"),
+            width: None,
+            ratio: None,
+            samples: None,
+            adaptive: None,
+            min_samples: 16,
+            heatmap: None,
+            denoise: false,
+            aov: Vec::new(),
+            max_depth: None,
+            exposure: None,
+            tone_map: None,
+            output: None,
+            format: None,
+            exr_compression: None,
+            seed: None,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            light_sampling: true,
+            progress: true,
+            stats_json: None,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(10 * 60),
+            checkpoint_samples: None,
+            resume: None,
+            frames: None,
+            resume_frames: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{USAGE}");
+                    process::exit(0);
+                }
+                "--scene" => options.scene = value()?,
+                "-w" | "--width" => options.width = Some(positive(&arg, &value()?)?),
+                "-r" | "--ratio" => options.ratio = Some(ratio(&value()?)?),
+                "-s" | "--samples" => options.samples = Some(positive(&arg, &value()?)?),
+                "--adaptive" => {
+                    let threshold: f64 = number(&arg, &value()?)?;
+                    if !(threshold.is_finite() && threshold > 0.0) {
+                        return Err(format!("{arg} must be greater than zero"));
+                    }
+                    options.adaptive = Some(threshold);
+                }
+                "--min-samples" => options.min_samples = positive(&arg, &value()?)?,
+                "--heatmap" => options.heatmap = Some(value()?),
+                "--denoise" => options.denoise = true,
+                "--aov" => {
+                    options.aov = value()?
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()?
+                }
+                "-d" | "--max-depth" => options.max_depth = Some(positive(&arg, &value()?)?),
+                "--exposure" => {
+                    let stops: f64 = number(&arg, &value()?)?;
+                    if !Display::valid_exposure(stops) {
+                        return Err(format!("invalid value for {arg}: {stops}"));
+                    }
+                    options.exposure = Some(stops);
+                }
+                "--tone-map" => options.tone_map = Some(value()?.parse()?),
+                "-o" | "--output" => options.output = Some(value()?),
+                "-f" | "--format" => options.format = Some(value()?.parse()?),
+                "--exr-compression" => options.exr_compression = Some(value()?.parse()?),
+                "--seed" => options.seed = Some(number(&arg, &value()?)?),
+                "-t" | "--threads" => options.threads = positive(&arg, &value()?)? as usize,
+                "--no-light-sampling" => options.light_sampling = false,
+                "-q" | "--quiet" => options.progress = false,
+                "--progress" => options.progress = true,
+                "--stats-json" => options.stats_json = Some(value()?),
+                "--checkpoint" => options.checkpoint = Some(value()?),
+                "--checkpoint-minutes" => {
+                    let minutes: f64 = number(&arg, &value()?)?;
+                    if !(minutes.is_finite() && minutes > 0.0) {
+                        return Err(format!("{arg} must be greater than zero"));
+                    }
+                    options.checkpoint_interval = Duration::from_secs_f64(minutes * 60.0);
+                }
+                "--checkpoint-samples" => {
+                    options.checkpoint_samples = Some(positive(&arg, &value()?)?)
+                }
+                "--resume" => options.resume = Some(value()?),
+                "--frames" => options.frames = Some(frames(&value()?)?),
+                "--resume-frames" => options.resume_frames = true,
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+                _ => options.scene = arg,
+            }
+        }
+        Ok(options)
+    }
+
+    /// 输出格式，没有指定也不能从扩展名判断的时候返回错误，输出到标准输出时默认是 PPM
+    pub fn format(&self) -> Result<Format, String> {
+        let format = match (self.format, &self.output) {
+            (Some(format), _) => format,
+            (None, Some(path)) => Format::from_path(path).ok_or_else(|| {
+                format!("cannot determine the output format of {path}, use --format")
+            })?,
+            (None, None) => Format::Ppm,
+        };
+        match (format, self.exr_compression) {
+            (Format::Exr(_), Some(compression)) => Ok(Format::Exr(compression)),
+            _ => Ok(format),
+        }
+    }
+
+    /// 用命令行参数覆盖场景文件里的图片设置，并检查设置是否合法
+    pub fn apply(&self, image: &mut ImageSettings) -> Result<(), String> {
+        image.width = self.width.unwrap_or(image.width);
+        image.ratio = self.ratio.unwrap_or(image.ratio);
+        image.samples = self.samples.unwrap_or(image.samples);
+        image.max_depth = self.max_depth.unwrap_or(image.max_depth);
+        image.display.exposure = self.exposure.unwrap_or(image.display.exposure);
+        image.display.tone_map = self.tone_map.unwrap_or(image.display.tone_map);
+
+        if image.width == 0 {
+            return Err(String::from("image width must be greater than zero"));
+        }
+        if !(image.ratio.is_finite() && image.ratio > 0.0) {
+            return Err(format!("invalid aspect ratio: {}", image.ratio));
+        }
+        if image.height() == 0 {
+            let message = "gives an image height of zero";
+            return Err(format!("aspect ratio {} {message}", image.ratio));
+        }
+        if image.samples == 0 {
+            return Err(String::from("samples per pixel must be greater than zero"));
+        }
+        if image.max_depth == 0 {
+            return Err(String::from("max depth must be greater than zero"));
+        }
+        Ok(())
+    }
+}
+
+fn number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {name}: {value}"))
+}
+
+fn positive(name: &str, value: &str) -> Result<u64, String> {
+    match number(name, value)? {
+        0 => Err(format!("{name} must be greater than zero")),
+        value => Ok(value),
+    }
+}
+
+// 帧的范围写成 `开始:结束`，包含结束的一帧
+fn frames(value: &str) -> Result<(u64, u64), String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or(format!("invalid frame range: {value}"))?;
+    let (start, end) = (number("--frames", start)?, number("--frames", end)?);
+    match start <= end {
+        true => Ok((start, end)),
+        false => Err(format!("invalid frame range: {value}")),
+    }
+}
+
+// 宽高比可以写成小数，也可以写成 `宽:高`
+fn ratio(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => {
+            number::<f64>("--ratio", width)? / number::<f64>("--ratio", height)?
+        }
+        None => number("--ratio", value)?,
+    };
+    match ratio.is_finite() && ratio > 0.0 {
+        true => Ok(ratio),
+        false => Err(format!("invalid aspect ratio: {value}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse_from(args.split_whitespace().map(String::from))
+    }
+
+    fn error(args: &str) -> String {
+        parse(args)
+            .err()
+            .unwrap_or_else(|| panic!("{args} should be rejected"))
+    }
+
+    // 解析命令行之后覆盖默认的图片设置
+    fn apply(args: &str) -> Result<ImageSettings, String> {
+        let mut image = ImageSettings::default();
+        parse(args)?.apply(&mut image)?;
+        Ok(image)
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse("scene.toml -w 320 --ratio 16:9 -s 8 --frames 2:5 -q").unwrap();
+        assert_eq!(options.scene, "scene.toml");
+        assert_eq!((options.width, options.samples), (Some(320), Some(8)));
+        assert_eq!(options.ratio, Some(16.0 / 9.0));
+        assert_eq!(options.frames, Some((2, 5)));
+        assert!(!options.progress);
+
+        let image = apply("-w 320 -r 2").unwrap();
+        assert_eq!((image.width, image.height()), (320, 160));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for args in ["--samples 0", "-s -1", "-w 0", "-t 0", "--min-samples 0"] {
+            error(args);
+        }
+        for args in ["--ratio 16:0", "--ratio 0", "-r -2", "-r 1:x", "-r nan"] {
+            error(args);
+        }
+        for args in ["--adaptive 0", "--checkpoint-minutes -1", "--exposure 2000"] {
+            error(args);
+        }
+        // 宽高比本身合法，但是和宽度一起得到的高度是 0
+        assert!(parse("-w 3 -r 4").is_ok());
+        let message = apply("-w 3 -r 4").err().unwrap();
+        assert!(message.contains("height of zero"), "{message}");
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_missing_values() {
+        let message = error("--no-such-option");
+        assert!(message.contains("unknown option"), "{message}");
+        for args in ["--samples", "-w", "--output", "scene.toml --frames"] {
+            let message = error(args);
+            assert!(message.contains("missing value"), "{args}: {message}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_frame_ranges() {
+        assert_eq!(parse("--frames 3:3").unwrap().frames, Some((3, 3)));
+        for range in ["5:2", "5", "1:", ":4", "a:b", "1:2:3", "-1:4"] {
+            let message = error(&format!("--frames {range}"));
+            assert!(message.contains("frame"), "{range}: {message}");
+        }
+    }
+}
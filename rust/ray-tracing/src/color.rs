@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use crate::vector3::Color;
+
+// 把线性的 HDR 颜色映射到 [0, 1] 的方法
+#[derive(Clone, Copy, Default)]
+pub enum ToneMap {
+    // 直接截断，超过 1 的部分都是白色
+    #[default]
+    Clamp,
+    // L / (1 + L)，按照亮度缩放，不改变颜色的色调
+    Reinhard,
+    // ACES 电影曲线（Narkowicz 的近似），高光平滑地过渡到白色
+    Aces,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("unknown tone mapping: {s}")),
+        }
+    }
+}
+
+impl ToneMap {
+    fn apply(self, color: Color) -> Color {
+        let color = match self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => color / (1.0 + color.luminance().max(0.0)),
+            ToneMap::Aces => {
+                let aces = |x: f64| {
+                    let x = x.max(0.0);
+                    x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)
+                };
+                Color::new(aces(color.x), aces(color.y), aces(color.z))
+            }
+        };
+        Color::new(
+            color.x.clamp(0.0, 1.0),
+            color.y.clamp(0.0, 1.0),
+            color.z.clamp(0.0, 1.0),
+        )
+    }
+}
+
+// 线性颜色转换成输出图片的方式：曝光、色调映射，最后用 sRGB 编码
+#[derive(Clone, Copy, Default)]
+pub struct Display {
+    // 曝光补偿（档），每加 1 亮度翻倍
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+}
+
+impl Display {
+    /// 曝光补偿换算成的倍数必须是有限的正数，太大或者太小的补偿会让颜色变成无穷大或者 0
+    pub fn valid_exposure(stops: f64) -> bool {
+        stops.exp2().is_normal()
+    }
+
+    /// 把线性的颜色转换成 8 位的 sRGB
+    pub fn encode(&self, color: Color) -> [u8; 3] {
+        let color = self.tone_map.apply(color * self.exposure.exp2());
+        [color.x, color.y, color.z].map(|value| (srgb(value) * 255.0).round() as u8)
+    }
+
+    /// 亮度是 luminance 的灰色最终显示的值（[0, 1]，没有量化成 8 位），用来估计显示出来的误差
+    pub fn gray(&self, luminance: f64) -> f64 {
+        let color = Color::new(luminance, luminance, luminance);
+        srgb(self.tone_map.apply(color * self.exposure.exp2()).x)
+    }
+}
+
+/// sRGB 的传递函数，把 [0, 1] 的线性值编码成显示的值
+pub fn srgb(linear: f64) -> f64 {
+    match linear <= 0.0031308 {
+        true => 12.92 * linear,
+        false => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+/// sRGB 编码的 8 位值转换回线性值，读取图片纹理时使用
+pub fn linear(encoded: u8) -> f64 {
+    let value = encoded as f64 / 255.0;
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for encoded in 0..=255u8 {
+            let value = srgb(linear(encoded)) * 255.0;
+            assert_eq!(value.round() as u8, encoded);
+        }
+        // 两段在分界点连续
+        assert!((1.055 * 0.0031308f64.powf(1.0 / 2.4) - 0.055 - 12.92 * 0.0031308).abs() < 1e-4);
+    }
+
+    #[test]
+    fn tone_maps_stay_in_range_and_keep_order() {
+        for tone_map in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::Aces] {
+            let mut previous = 0.0;
+            for i in 0..=1000 {
+                let value = i as f64 * 0.05;
+                let mapped = tone_map.apply(Color::new(value, value, value)).x;
+                assert!((0.0..=1.0).contains(&mapped));
+                assert!(mapped >= previous);
+                previous = mapped;
+            }
+        }
+        // 只有截断会让很亮的颜色完全变白
+        let bright = Color::new(8.0, 4.0, 2.0);
+        assert_eq!(ToneMap::Clamp.apply(bright).z, 1.0);
+        assert!(ToneMap::Reinhard.apply(bright).z < 1.0);
+        assert!(ToneMap::Aces.apply(bright).z < 1.0);
+    }
+}
@@ -0,0 +1,475 @@
+// zlib 压缩和解压（RFC 1950 / RFC 1951）。
+// 压缩使用 LZ77 查找重复的字符串，再用固定的霍夫曼编码输出，
+// 压缩率比不上 zlib 的动态霍夫曼编码，但是足够把图片压缩到合理的大小。
+
+// 滑动窗口的大小
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// 在哈希链上最多查找的次数，越大压缩率越高，速度越慢
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// 压缩成 zlib 格式（带两个字节的头和 Adler-32 校验）
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // CM = 8（deflate），CINFO = 7（32K 窗口），FCHECK 使头部是 31 的倍数
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// 压缩成原始的 deflate 数据，只有一个使用固定霍夫曼编码的块
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // BFINAL = 1，BTYPE = 01（固定霍夫曼编码）
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+
+    let mut i = 0;
+    while i < data.len() {
+        let (length, distance) = longest_match(data, i, &head, &prev);
+
+        let step = match length >= MIN_MATCH {
+            true => {
+                writer.length(length);
+                writer.distance(distance);
+                length
+            }
+            false => {
+                writer.literal(data[i] as u16);
+                1
+            }
+        };
+
+        // 把经过的每个位置都加入哈希链
+        for position in i..(i + step).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            let hash = hash(&data[position..]);
+            prev[position % WINDOW] = head[hash];
+            head[hash] = position;
+        }
+        i += step;
+    }
+
+    // 块结束
+    writer.literal(256);
+    writer.finish()
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+// 在窗口里找最长的匹配，返回长度和距离
+fn longest_match(data: &[u8], i: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if i + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max = MAX_MATCH.min(data.len() - i);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(&data[i..])];
+
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || i - candidate > WINDOW - 1 {
+            break;
+        }
+
+        let length = data[candidate..candidate + max]
+            .iter()
+            .zip(&data[i..i + max])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = i - candidate;
+            if length == max {
+                break;
+            }
+        }
+
+        let next = prev[candidate % WINDOW];
+        // 链上的位置必须越来越远，否则是被覆盖掉的旧数据
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+    }
+    (best_length, best_distance)
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 每 5552 个字节取一次模，不会溢出
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+// 按照 deflate 的要求从低位开始写入比特
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // 霍夫曼编码从高位开始写，需要先反转
+    fn write_code(&mut self, code: u32, bits: u32) {
+        self.write(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    // 固定霍夫曼编码的字面量和长度符号
+    fn literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let index = LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1;
+        self.literal(257 + index as u16);
+        let extra = length - LENGTH_BASE[index] as usize;
+        self.write(extra as u32, LENGTH_EXTRA[index] as u32);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+        self.write_code(index as u32, 5);
+        let extra = distance - DISTANCE_BASE[index] as usize;
+        self.write(extra as u32, DISTANCE_EXTRA[index] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// 解压 zlib 格式的数据，检查头部和 Adler-32 校验
+pub fn unzlib(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6
+        || data[0] & 0x0F != 8
+        || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31)
+    {
+        return Err(String::from("invalid zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(String::from("zlib preset dictionary is not supported"));
+    }
+
+    let out = inflate(&data[2..])?;
+    let checksum = data[data.len() - 4..].try_into().unwrap();
+    match adler32(&out) == u32::from_be_bytes(checksum) {
+        true => Ok(out),
+        false => Err(String::from("zlib checksum mismatch")),
+    }
+}
+
+/// 解压原始的 deflate 数据，支持不压缩、固定霍夫曼和动态霍夫曼三种块
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader {
+        data,
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // 不压缩的块从下一个字节开始
+                reader.align();
+                let length = reader.bits(16)?;
+                let complement = reader.bits(16)?;
+                if length != !complement & 0xFFFF {
+                    return Err(String::from("invalid stored block length"));
+                }
+                for _ in 0..length {
+                    out.push(reader.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                reader.block(&mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = reader.dynamic_tables()?;
+                reader.block(&mut out, &literals, &distances)?;
+            }
+            _ => return Err(String::from("invalid deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// 范式霍夫曼编码的解码表：每种长度的编码个数和按编码排好序的符号
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, bits: u32) -> Result<u32, String> {
+        while self.count < bits {
+            let Some(&byte) = self.data.get(self.position) else {
+                return Err(String::from("unexpected end of deflate data"));
+            };
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1 << bits) - 1)) as u32;
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    // 霍夫曼编码从高位开始，每次读一位
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid huffman code"))
+    }
+
+    fn dynamic_tables(&mut self) -> Result<(Huffman, Huffman), String> {
+        const ORDER: [usize; 19] = [
+            16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+        ];
+
+        let literals = self.bits(5)? as usize + 257;
+        let distances = self.bits(5)? as usize + 1;
+        let codes = self.bits(4)? as usize + 4;
+
+        let mut lengths = [0; 19];
+        for &index in &ORDER[..codes] {
+            lengths[index] = self.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&lengths);
+
+        // 字面量和距离的编码长度连在一起，重复的长度也可以跨过两者的边界
+        let mut lengths = Vec::with_capacity(literals + distances);
+        while lengths.len() < literals + distances {
+            let (length, repeat) = match self.decode(&code_lengths)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => match lengths.last() {
+                    Some(&last) => (last, 3 + self.bits(2)?),
+                    None => return Err(String::from("repeat without a previous length")),
+                },
+                17 => (0, 3 + self.bits(3)?),
+                _ => (0, 11 + self.bits(7)?),
+            };
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths.len() > literals + distances {
+            return Err(String::from("too many code lengths"));
+        }
+
+        let (literal, distance) = lengths.split_at(literals);
+        Ok((Huffman::new(literal), Huffman::new(distance)))
+    }
+
+    fn block(
+        &mut self,
+        out: &mut Vec<u8>,
+        literals: &Huffman,
+        distances: &Huffman,
+    ) -> Result<(), String> {
+        loop {
+            let symbol = self.decode(literals)? as usize;
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => return Ok(()),
+                257..=285 => {
+                    let index = symbol - 257;
+                    let length = LENGTH_BASE[index] as usize
+                        + self.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                    let index = self.decode(distances)? as usize;
+                    if index >= DISTANCE_BASE.len() {
+                        return Err(String::from("invalid distance code"));
+                    }
+                    let distance = DISTANCE_BASE[index] as usize
+                        + self.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err(String::from("distance too far back"));
+                    }
+
+                    // 重复的部分可能和正在复制的部分重叠，只能一个一个复制
+                    let start = out.len() - distance;
+                    for i in 0..length {
+                        out.push(out[start + i]);
+                    }
+                }
+                _ => return Err(String::from("invalid literal/length code")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // 超过 5552 个字节才会分段取模
+        let long = vec![0xFF; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &long {
+            a = (a + byte as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&long), (b << 16 | a) as u32);
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let mut sampler = Sampler::new(1);
+        let random: Vec<u8> = (0..5000).map(|_| (sampler.gen() * 256.0) as u8).collect();
+        // 重复的部分相隔很远，超过窗口的匹配不能使用
+        let mut distant = random.clone();
+        distant.extend(vec![7; 40_000]);
+        distant.extend(&random);
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(50);
+
+        let inputs: [&[u8]; 6] = [b"", b"a", &[0; 1000], &random, &distant, &text];
+        for data in inputs {
+            let compressed = zlib(data);
+            assert_eq!(unzlib(&compressed).unwrap(), data);
+            assert_eq!(inflate(&deflate(data)).unwrap(), data);
+        }
+        // 重复的数据能压缩得很小
+        assert!(zlib(&[0; 1000]).len() < 30);
+        assert!(zlib(&text).len() < text.len() / 10);
+    }
+
+    #[test]
+    fn inflates_zlib_streams() {
+        // zlib 库输出的不压缩的块和动态霍夫曼编码的块
+        let stored = [
+            120, 1, 1, 17, 0, 238, 255, 104, 101, 108, 108, 111, 32, 104, 101, 108, 108, 111, 32,
+            104, 101, 108, 108, 111, 58, 46, 6, 125,
+        ];
+        assert_eq!(unzlib(&stored).unwrap(), b"hello hello hello");
+        let dynamic = [
+            120, 218, 5, 193, 1, 10, 0, 32, 8, 4, 176, 183, 42, 89, 18, 28, 69, 29, 165, 189, 190,
+            77, 214, 157, 165, 158, 183, 34, 91, 151, 154, 240, 80, 108, 170, 13, 130, 195, 148,
+            27, 26, 142, 252, 96, 52, 17, 41,
+        ];
+        assert_eq!(
+            unzlib(&dynamic).unwrap(),
+            b"arwpdfvzrxygjafymhxbmstbeotmtoebtsmbxhmy"
+        );
+
+        // 截断和校验和错误
+        assert!(unzlib(&stored[..stored.len() - 6]).is_err());
+        let mut corrupt = stored;
+        corrupt[10] ^= 1;
+        assert!(unzlib(&corrupt).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use std::thread;
+
+use crate::aov::{Buffers, Pixel};
+use crate::film::Film;
+use crate::material::Kind;
+use crate::vector3::Color;
+
+// 边缘保持的 à-trous 小波降噪（Dammertz 等 2010，方差引导的部分参考 SVGF）。
+// 在 HDR 的累加缓冲区上进行：先除掉反照率得到光照，每一轮用 5x5 的 B3 样条核做加权平均，
+// 邻居的权重由法线、深度和亮度的差别决定，间隔每一轮加倍，最后再乘回反照率。
+
+// 五轮之后的范围是 61x61 个像素
+const ITERATIONS: u32 = 5;
+
+// B3 样条核 1/16、1/4、3/8、1/4、1/16，按照到中心的距离取值
+const KERNEL: [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// 亮度的差别超过几倍标准差之后权重很快变小
+const SIGMA_LUMINANCE: f64 = 4.0;
+
+const SIGMA_DEPTH: f64 = 1.0;
+
+// 法线夹角的余弦的次方，不同朝向的表面几乎不会混在一起
+const NORMAL_POWER: i32 = 128;
+
+// 反照率不同的地方通常是不同的材质
+const SIGMA_ALBEDO: f64 = 0.1;
+
+// 反照率很小的时候除掉它会放大噪点
+const MIN_ALBEDO: f64 = 0.01;
+
+// 去掉反照率之后的光照，以及它的均值的方差（亮度）
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    color: Color,
+    variance: f64,
+}
+
+/// 按照特征缓冲区对累加缓冲区降噪，返回的缓冲区的采样数不变，颜色换成降噪后的结果。
+/// 特征缓冲区的大小必须和累加缓冲区一样。
+pub fn denoise(film: &Film, features: &Buffers, threads: usize) -> Film {
+    assert_eq!((film.width, film.height), (features.width, features.height));
+    let (width, height) = (film.width as usize, film.height as usize);
+
+    let albedos: Vec<Color> = features
+        .pixels
+        .iter()
+        .map(|pixel| {
+            let albedo = pixel.albedo;
+            Color::new(
+                albedo.x.max(MIN_ALBEDO),
+                albedo.y.max(MIN_ALBEDO),
+                albedo.z.max(MIN_ALBEDO),
+            )
+        })
+        .collect();
+
+    let mut samples: Vec<Sample> = (0..width * height)
+        .map(|i| {
+            let count = film.counts[i] as f64;
+            let mean = film.colors[i] / count.max(1.0);
+            let albedo = albedos[i];
+            let luminance = mean.luminance();
+            // 只有一次采样的时候无法估计方差，只按照几何特征降噪
+            let variance = match film.counts[i] {
+                0 | 1 => f64::INFINITY,
+                _ => (film.squares[i] / count - luminance * luminance).max(0.0) / count,
+            };
+            Sample {
+                color: Color::new(mean.x / albedo.x, mean.y / albedo.y, mean.z / albedo.z),
+                variance: variance / albedo.luminance().powi(2),
+            }
+        })
+        .collect();
+
+    // 采样数很少的时候方差的估计很不可靠（比如四次采样都是黑色），
+    // 用周围同一个表面上的像素之间的差别作为下限
+    let spatial = spatial_variances(&samples, features);
+    for (sample, spatial) in samples.iter_mut().zip(spatial) {
+        sample.variance = sample.variance.max(spatial);
+    }
+
+    let gradients = depth_gradients(features);
+    let rows = height.div_ceil(threads.max(1)).max(1);
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        let mut filtered = vec![Sample::default(); width * height];
+        let current = &samples;
+        let gradients = &gradients;
+        thread::scope(|scope| {
+            for (chunk, filtered) in filtered.chunks_mut(rows * width).enumerate() {
+                scope.spawn(move || {
+                    for (offset, sample) in filtered.iter_mut().enumerate() {
+                        let index = chunk * rows * width + offset;
+                        *sample = filter(current, features, gradients, index, step);
+                    }
+                });
+            }
+        });
+        samples = filtered;
+    }
+
+    let mut result = Film::new(film.width, film.height, film.seed);
+    result.squares.clone_from(&film.squares);
+    result.counts.clone_from(&film.counts);
+    for (i, color) in result.colors.iter_mut().enumerate() {
+        *color = film.counts[i] as f64 * (samples[i].color * albedos[i]);
+    }
+    result
+}
+
+// 一个像素和它的 5x5 个邻居（间隔 step）的加权平均
+fn filter(
+    samples: &[Sample],
+    features: &Buffers,
+    gradients: &[f64],
+    index: usize,
+    step: i64,
+) -> Sample {
+    let (width, height) = (features.width as i64, features.height as i64);
+    let (x, y) = (index as i64 % width, index as i64 / width);
+    let center = &features.pixels[index];
+    let hit = center.depth.is_finite();
+    let luminance = samples[index].color.luminance();
+    let sigma = SIGMA_LUMINANCE * samples[index].variance.sqrt() + 1e-6;
+
+    let (mut color, mut variance, mut total) = (Color::default(), 0.0, 0.0);
+    for dy in -2i64..=2 {
+        for dx in -2i64..=2 {
+            let (qx, qy) = (x + dx * step, y + dy * step);
+            if !(0..width).contains(&qx) || !(0..height).contains(&qy) {
+                continue;
+            }
+            let q = (qy * width + qx) as usize;
+            let other = &features.pixels[q];
+            if !compatible(center, other) {
+                continue;
+            }
+
+            let mut weight =
+                KERNEL[dx.unsigned_abs() as usize] * KERNEL[dy.unsigned_abs() as usize];
+            // 中心像素的几何特征的权重总是 1，法线是零向量或者深度是 0 的时候也不会被排除
+            if hit && q != index {
+                weight *= center.normal.dot(other.normal).max(0.0).powi(NORMAL_POWER);
+                // 允许的深度差别和深度的梯度、到中心的距离成正比，斜着的表面不会被当成边缘
+                let distance = step as f64 * ((dx * dx + dy * dy) as f64).sqrt();
+                let tolerance = SIGMA_DEPTH * gradients[index] * distance + 1e-3 * center.depth;
+                let tolerance = tolerance.max(f64::MIN_POSITIVE);
+                weight *= (-(center.depth - other.depth).abs() / tolerance).exp();
+                let difference = center.albedo - other.albedo;
+                weight *= (-difference.dot(difference) / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp();
+            }
+            weight *= (-(luminance - samples[q].color.luminance()).abs() / sigma).exp();
+            if weight == 0.0 {
+                continue;
+            }
+
+            color += weight * samples[q].color;
+            variance += weight * weight * samples[q].variance;
+            total += weight;
+        }
+    }
+
+    // 中心像素只剩下核和亮度的权重，亮度的差别是 0，所以 total 一定大于 0
+    Sample {
+        color: color / total,
+        variance: variance / (total * total),
+    }
+}
+
+// 命中物体的像素和背景不混在一起，光源本身没有噪点，也不和周围的表面混在一起
+fn compatible(center: &Pixel, other: &Pixel) -> bool {
+    let emissive = |pixel: &Pixel| matches!(pixel.material, Some(Kind::DiffuseLight));
+    center.depth.is_finite() == other.depth.is_finite() && emissive(center) == emissive(other)
+}
+
+// 以每个像素为中心的 5x5 范围里，朝向相同的像素的亮度的方差
+fn spatial_variances(samples: &[Sample], features: &Buffers) -> Vec<f64> {
+    let (width, height) = (features.width as i64, features.height as i64);
+    (0..samples.len())
+        .map(|index| {
+            let (x, y) = (index as i64 % width, index as i64 / width);
+            let center = &features.pixels[index];
+            let (mut sum, mut square, mut count) = (0.0, 0.0, 0.0);
+            for qy in (y - 2).max(0)..(y + 3).min(height) {
+                for qx in (x - 2).max(0)..(x + 3).min(width) {
+                    let q = (qy * width + qx) as usize;
+                    let other = &features.pixels[q];
+                    let same = !center.depth.is_finite() || center.normal.dot(other.normal) > 0.9;
+                    if compatible(center, other) && same {
+                        let luminance = samples[q].color.luminance();
+                        sum += luminance;
+                        square += luminance * luminance;
+                        count += 1.0;
+                    }
+                }
+            }
+            let mean = sum / count;
+            (square / count - mean * mean).max(0.0)
+        })
+        .collect()
+}
+
+// 每个像素的深度在屏幕上每移动一个像素的变化，用中心差分估计
+fn depth_gradients(features: &Buffers) -> Vec<f64> {
+    let (width, height) = (features.width as usize, features.height as usize);
+    let depth = |x: usize, y: usize, center: f64| {
+        let depth = features.pixels[y * width + x].depth;
+        match depth.is_finite() {
+            true => depth,
+            false => center,
+        }
+    };
+
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let center = features.pixels[i].depth;
+            if !center.is_finite() {
+                return 0.0;
+            }
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let dx =
+                (depth(right, y, center) - depth(left, y, center)) / (right - left).max(1) as f64;
+            let dy = (depth(x, down, center) - depth(x, up, center)) / (down - up).max(1) as f64;
+            (dx * dx + dy * dy).sqrt()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{self, Settings};
+    use crate::sampler::Sampler;
+    use crate::vector3::Vector3;
+    use crate::{aov, bvh, scene};
+
+    // 和参考图片（很多次采样）转换成显示的颜色（gamma 2）之后的均方差
+    fn error(film: &Film, reference: &Film) -> f64 {
+        let mean = |film: &Film, i: usize| {
+            let color = film.colors[i] / film.counts[i] as f64;
+            let display = |value: f64| value.clamp(0.0, 1.0).sqrt();
+            Color::new(display(color.x), display(color.y), display(color.z))
+        };
+        let sum: f64 = (0..film.colors.len())
+            .map(|i| {
+                let difference = mean(film, i) - mean(reference, i);
+                difference.dot(difference)
+            })
+            .sum();
+        sum / film.colors.len() as f64
+    }
+
+    #[test]
+    fn denoising_moves_closer_to_reference() {
+        let scene = scene::builtin("cornell", &mut Sampler::new(0)).unwrap();
+        let (width, height) = (48, 48);
+        let camera = scene.camera.camera(1.0);
+        let world = bvh::accelerate(scene.world);
+        let mut settings = Settings {
+            width,
+            height,
+            samples: 128,
+            max_depth: 4,
+            background: scene.background,
+            lights: scene.lights,
+            environment: None,
+            threads: 4,
+            progress: false,
+            checkpoint: None,
+            adaptive: None,
+        };
+
+        let mut reference = Film::new(width, height, 1);
+        render::render(&world, &camera, &settings, &mut reference);
+
+        settings.samples = 4;
+        let mut noisy = Film::new(width, height, 2).with_features();
+        render::render(&world, &camera, &settings, &mut noisy);
+        let features = aov::Buffers::new(&noisy).unwrap();
+        let denoised = denoise(&noisy, &features, 4);
+
+        let (before, after) = (error(&noisy, &reference), error(&denoised, &reference));
+        // 不同的种子误差大约减少到三分之一，留一些余量
+        assert!(after * 2.5 < before, "{after} vs {before}");
+    }
+
+    #[test]
+    fn degenerate_features_do_not_give_nan() {
+        let (width, height) = (3, 3);
+        let mut film = Film::new(width, height, 0);
+        for i in 0..film.colors.len() {
+            let value = i as f64 / 8.0;
+            film.colors[i] = 4.0 * Color::new(value, value, value);
+            film.squares[i] = 4.0 * value * value;
+            film.counts[i] = 4;
+        }
+        // 法线是零向量、深度是 0 的像素，几何特征的权重都是 0 或者 0/0
+        let pixel = Pixel {
+            normal: Vector3::new(0.0, 0.0, 0.0),
+            depth: 0.0,
+            albedo: Color::new(0.5, 0.5, 0.5),
+            material: None,
+            object: 0,
+        };
+        let features = Buffers {
+            width,
+            height,
+            pixels: vec![pixel; (width * height) as usize],
+        };
+        let denoised = denoise(&film, &features, 1);
+        for color in &denoised.colors {
+            assert!(color.x.is_finite() && color.y.is_finite() && color.z.is_finite());
+        }
+    }
+}
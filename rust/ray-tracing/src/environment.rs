@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+
+use crate::hdr::HdrImage;
+use crate::matrix::Matrix4;
+use crate::sampler::Sampler;
+use crate::vector3::{Color, Vector3};
+
+// 等距柱状投影（经纬度）的环境贴图，包围整个场景，射线没有命中物体时按照方向查找颜色。
+// 图片的 u 是经度，中间对着 -z 方向，v 是从上（+y）到下的纬度。
+// 按照每个像素的亮度乘以它所占的立体角做重要性采样：先按照每一行的和选一行（边缘分布），
+// 再在这一行里选一个像素（条件分布），很亮的太阳也能直接采样到，不会产生萤火虫一样的亮点。
+pub struct Environment {
+    width: usize,
+    height: usize,
+    colors: Vec<Color>,
+    // 贴图坐标到世界坐标的旋转，以及它的逆
+    rotation: Matrix4,
+    inverse: Matrix4,
+    // 每一行像素的权重的累加和，每行 width + 1 个
+    conditional: Vec<f64>,
+    // 每一行的权重之和的累加和，height + 1 个
+    marginal: Vec<f64>,
+}
+
+impl Environment {
+    /// rotation 是绕 y 轴旋转的角度（度），intensity 是亮度的倍数
+    pub fn new(image: HdrImage, rotation: f64, intensity: f64) -> Environment {
+        let (width, height) = (image.width as usize, image.height as usize);
+        debug_assert!(width > 0 && height > 0, "环境贴图不能是空的");
+        let colors: Vec<Color> = image.pixels.iter().map(|&c| intensity * c).collect();
+
+        // 越靠近两极的像素占的立体角越小。全黑的贴图按照立体角均匀采样
+        let black = colors.iter().all(|color| color.luminance() <= 0.0);
+        let mut conditional = Vec::with_capacity(height * (width + 1));
+        let mut marginal = vec![0.0];
+        for row in 0..height {
+            let sin = (PI * (row as f64 + 0.5) / height as f64).sin();
+            let mut sum = 0.0;
+            conditional.push(sum);
+            for color in &colors[row * width..(row + 1) * width] {
+                sum += match black {
+                    true => sin,
+                    false => color.luminance().max(0.0) * sin,
+                };
+                conditional.push(sum);
+            }
+            marginal.push(marginal[row] + sum);
+        }
+
+        let rotation = Matrix4::rotation(Vector3::new(0.0, 1.0, 0.0), rotation);
+        Environment {
+            width,
+            height,
+            colors,
+            rotation,
+            inverse: rotation.transpose(),
+            conditional,
+            marginal,
+        }
+    }
+
+    pub fn color(&self, direction: Vector3) -> Color {
+        let (column, row, _) = self.pixel(direction);
+        self.colors[row * self.width + column]
+    }
+
+    /// 按照亮度随机选一个方向
+    pub fn random_direction(&self, sampler: &mut Sampler) -> Vector3 {
+        let row = search(&self.marginal, sampler.gen());
+        let start = row * (self.width + 1);
+        let column = search(
+            &self.conditional[start..start + self.width + 1],
+            sampler.gen(),
+        );
+
+        // 在像素里均匀选一个位置
+        let u = (column as f64 + sampler.gen()) / self.width as f64;
+        let v = (row as f64 + sampler.gen()) / self.height as f64;
+        let (theta, phi) = (PI * v, 2.0 * PI * u - PI);
+        let local = Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        self.rotation.vector(local)
+    }
+
+    /// random_direction 采样到 direction 的概率密度（立体角）
+    pub fn pdf(&self, direction: Vector3) -> f64 {
+        let (column, row, sin) = self.pixel(direction);
+        let total = self.marginal[self.height];
+        if sin <= 0.0 || total <= 0.0 {
+            return 0.0;
+        }
+        let start = row * (self.width + 1) + column;
+        let weight = self.conditional[start + 1] - self.conditional[start];
+        // 贴图坐标上的概率密度是 weight / total * 像素数，
+        // 贴图坐标到立体角的面积比是 2π² sinθ
+        let pdf = weight / total * (self.width * self.height) as f64;
+        pdf / (2.0 * PI * PI * sin)
+    }
+
+    // 方向对应的像素的列、行，以及 sinθ
+    fn pixel(&self, direction: Vector3) -> (usize, usize, f64) {
+        let local = self.inverse.vector(direction).unit();
+        let theta = local.y.clamp(-1.0, 1.0).acos();
+        let phi = local.x.atan2(-local.z);
+        let u = (phi + PI) / (2.0 * PI);
+        let v = theta / PI;
+        let column = ((u * self.width as f64) as usize).min(self.width - 1);
+        let row = ((v * self.height as f64) as usize).min(self.height - 1);
+        (column, row, theta.sin())
+    }
+}
+
+// 在累加和里找到 [cdf[i], cdf[i + 1]) 包含 xi * 总和的 i，跳过权重是 0 的区间
+fn search(cdf: &[f64], xi: f64) -> usize {
+    let target = xi * cdf[cdf.len() - 1];
+    let index = cdf.partition_point(|&sum| sum <= target);
+    index.clamp(1, cdf.len() - 1) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 一张很暗的贴图上有一个很亮的“太阳”
+    fn sun() -> Environment {
+        let (width, height) = (32, 16);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[5 * width + 20] = Color::new(5000.0, 4000.0, 3000.0);
+        let image = HdrImage {
+            width: width as u64,
+            height: height as u64,
+            pixels,
+        };
+        Environment::new(image, 30.0, 2.0)
+    }
+
+    #[test]
+    fn sampled_directions_match_pdf() {
+        let environment = sun();
+        let mut sampler = Sampler::new(5);
+
+        // 按照 pdf 采样，颜色 / pdf 的期望是整个球面上的积分
+        let count = 200_000;
+        let mut estimate = 0.0;
+        for _ in 0..count {
+            let direction = environment.random_direction(&mut sampler);
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            let pdf = environment.pdf(direction);
+            assert!(pdf > 0.0);
+            estimate += environment.color(direction).luminance() / pdf;
+        }
+        estimate /= count as f64;
+
+        // 均匀采样的估计，作为参考
+        let mut reference = 0.0;
+        for _ in 0..count {
+            let direction = Vector3::random_unit(&mut sampler);
+            reference += environment.color(direction).luminance() * 4.0 * PI;
+        }
+        reference /= count as f64;
+
+        // 精确的积分：每个像素的亮度乘以它的立体角
+        let mut exact = 0.0;
+        for row in 0..16 {
+            let (top, bottom) = (PI * row as f64 / 16.0, PI * (row + 1) as f64 / 16.0);
+            let solid_angle = 2.0 * PI / 32.0 * (top.cos() - bottom.cos());
+            for column in 0..32 {
+                exact += environment.colors[row * 32 + column].luminance() * solid_angle;
+            }
+        }
+        assert!(
+            (estimate - exact).abs() < 0.01 * exact,
+            "{estimate} vs {exact}"
+        );
+        assert!(
+            (reference - exact).abs() < 0.2 * exact,
+            "{reference} vs {exact}"
+        );
+    }
+
+    #[test]
+    fn rotation_moves_the_sun() {
+        let environment = sun();
+        let mut sampler = Sampler::new(7);
+        // 太阳占了大部分的能量，采样到的方向大多是同一个方向
+        let mut brightest = Vector3::default();
+        for _ in 0..100 {
+            let direction = environment.random_direction(&mut sampler);
+            if environment.color(direction).luminance() > 1000.0 {
+                brightest = direction;
+            }
+        }
+        assert!(environment.color(brightest).luminance() > 1000.0);
+
+        let unrotated = Environment::new(
+            HdrImage {
+                width: 32,
+                height: 16,
+                pixels: environment.colors.iter().map(|&c| c / 2.0).collect(),
+            },
+            0.0,
+            2.0,
+        );
+        let back = Matrix4::rotation(Vector3::new(0.0, 1.0, 0.0), -30.0).vector(brightest);
+        assert!(unrotated.color(back).luminance() > 1000.0);
+        assert!(unrotated.color(brightest).luminance() < 1.0);
+    }
+}
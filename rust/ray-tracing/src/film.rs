@@ -0,0 +1,259 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::aov;
+use crate::material::Kind;
+use crate::vector3::{Color, Vector3};
+
+// 检查点文件的开头，最后两位是版本号
+const MAGIC: &[u8; 8] = b"RTFILM03";
+// 文件头是 MAGIC 加上宽、高、种子和是否有辅助信息，每个像素是四个 f64 和一个 u64，
+// 有辅助信息的时候每个像素再加上十个 f64 或者 u64
+const HEADER_BYTES: u64 = 40;
+const PIXEL_BYTES: u64 = 40;
+const FEATURE_BYTES: u64 = 80;
+
+// 浮点数累加缓冲区，记录每个像素累加的颜色、亮度的平方和已经完成的采样数，
+// 可以保存到检查点文件，之后从文件恢复继续渲染。
+pub struct Film {
+    pub width: u64,
+    pub height: u64,
+    // 渲染使用的种子，恢复时必须使用相同的种子
+    pub seed: u64,
+    pub colors: Vec<Color>,
+    // 每次采样的亮度的平方的和，用来估计方差
+    pub squares: Vec<f64>,
+    pub counts: Vec<u64>,
+    // 辅助图像和降噪需要的第一次命中的信息，不需要的时候是 None
+    pub features: Option<Vec<aov::Sum>>,
+}
+
+impl Film {
+    pub fn new(width: u64, height: u64, seed: u64) -> Film {
+        let size = (width * height) as usize;
+        Film {
+            width,
+            height,
+            seed,
+            colors: vec![Color::default(); size],
+            squares: vec![0.0; size],
+            counts: vec![0; size],
+            features: None,
+        }
+    }
+
+    /// 渲染的时候同时累加辅助信息
+    pub fn with_features(mut self) -> Film {
+        let size = self.colors.len();
+        self.features = Some(vec![aov::Sum::default(); size]);
+        self
+    }
+
+    /// 所有像素中最少的采样数
+    pub fn samples(&self) -> u64 {
+        self.counts.iter().copied().min().unwrap_or(0)
+    }
+
+    /// 先写到临时文件再重命名，保存过程中被中断也不会破坏之前的检查点
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let temp = format!("{path}.tmp");
+        let mut out = BufWriter::new(File::create(&temp)?);
+
+        out.write_all(MAGIC)?;
+        let features = self.features.is_some() as u64;
+        for value in [self.width, self.height, self.seed, features] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        for ((color, square), count) in self.colors.iter().zip(&self.squares).zip(&self.counts) {
+            for value in [color.x, color.y, color.z, *square] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&count.to_le_bytes())?;
+        }
+        for sum in self.features.iter().flatten() {
+            let (n, albedo) = (sum.normal, sum.albedo);
+            for value in [n.x, n.y, n.z, sum.depth, albedo.x, albedo.y, albedo.z] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            // 材质的种类从 1 开始编号，0 表示没有命中
+            let material = sum.material.map_or(0, |kind| kind as u64 + 1);
+            for value in [sum.hits, material, sum.object as u64] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        out.into_inner()?.sync_all()?;
+
+        fs::rename(temp, path)
+    }
+
+    pub fn load(path: &str) -> io::Result<Film> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if magic[..6] != MAGIC[..6] {
+            let message = "not a checkpoint file";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        if &magic != MAGIC {
+            let message = "unsupported checkpoint version";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        let mut read = || -> io::Result<[u8; 8]> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(bytes)
+        };
+        let width = u64::from_le_bytes(read()?);
+        let height = u64::from_le_bytes(read()?);
+        let seed = u64::from_le_bytes(read()?);
+        let features = match u64::from_le_bytes(read()?) {
+            0 => false,
+            1 => true,
+            _ => {
+                let message = "invalid checkpoint header";
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        };
+
+        // 分配缓冲区之前先检查文件的长度，损坏的文件里的宽高可能非常大
+        let size = width
+            .checked_mul(height)
+            .filter(|&size| usize::try_from(size).is_ok());
+        let pixel_bytes = PIXEL_BYTES + features as u64 * FEATURE_BYTES;
+        let expected = size
+            .and_then(|size| size.checked_mul(pixel_bytes))
+            .and_then(|bytes| bytes.checked_add(HEADER_BYTES));
+        if expected != Some(length) {
+            let message = format!("checkpoint size does not match {width}x{height} pixels");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        let mut film = Film::new(width, height, seed);
+        let pixels = film.colors.iter_mut().zip(&mut film.squares);
+        for ((color, square), count) in pixels.zip(&mut film.counts) {
+            color.x = f64::from_le_bytes(read()?);
+            color.y = f64::from_le_bytes(read()?);
+            color.z = f64::from_le_bytes(read()?);
+            *square = f64::from_le_bytes(read()?);
+            *count = u64::from_le_bytes(read()?);
+        }
+        if features {
+            film = film.with_features();
+        }
+        for sum in film.features.iter_mut().flatten() {
+            let mut next = || read().map(f64::from_le_bytes);
+            sum.normal = Vector3::new(next()?, next()?, next()?);
+            sum.depth = next()?;
+            sum.albedo = Color::new(next()?, next()?, next()?);
+            sum.hits = u64::from_le_bytes(read()?);
+            let material = u64::from_le_bytes(read()?);
+            sum.material = match material {
+                0 => None,
+                _ => match Kind::ALL.get(material as usize - 1) {
+                    Some(&kind) => Some(kind),
+                    None => {
+                        let message = format!("invalid material kind {material} in checkpoint");
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                    }
+                },
+            };
+            sum.object = u64::from_le_bytes(read()?) as usize;
+        }
+        Ok(film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("film-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn film() -> Film {
+        let mut film = Film::new(5, 3, 42).with_features();
+        for i in 0..15 {
+            film.colors[i] = Color::new(i as f64, 0.5, -1.25);
+            film.squares[i] = i as f64 * 0.1;
+            film.counts[i] = i as u64 + 1;
+        }
+        for (i, sum) in film.features.as_mut().unwrap().iter_mut().enumerate() {
+            sum.normal = Vector3::new(0.0, 1.0, i as f64);
+            sum.depth = i as f64 * 2.5;
+            sum.hits = i as u64 / 2;
+            sum.albedo = Color::new(0.25, 0.5, i as f64);
+            sum.material = Kind::ALL.get(i % 6).copied();
+            sum.object = i;
+        }
+        film
+    }
+
+    #[test]
+    fn checkpoint_round_trips() {
+        let path = temp_path("round-trip");
+        let original = film();
+        original.save(&path).unwrap();
+        let loaded = Film::load(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (5, 3));
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.counts, original.counts);
+        assert_eq!(loaded.squares, original.squares);
+        for (a, b) in loaded.colors.iter().zip(&original.colors) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+        let features = loaded.features.unwrap();
+        for (a, b) in features.iter().zip(original.features.as_ref().unwrap()) {
+            assert_eq!(
+                (a.normal.z, a.depth, a.albedo.z),
+                (b.normal.z, b.depth, b.albedo.z)
+            );
+            assert_eq!((a.hits, a.object), (b.hits, b.object));
+            assert_eq!(a.material.map(|k| k as u8), b.material.map(|k| k as u8));
+        }
+
+        // 没有辅助信息的 Film
+        let mut plain = film();
+        plain.features = None;
+        plain.save(&path).unwrap();
+        let loaded = Film::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.features.is_none());
+        assert_eq!(loaded.counts, plain.counts);
+    }
+
+    #[test]
+    fn rejects_damaged_checkpoints() {
+        let path = temp_path("damaged");
+        film().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        // 截断的文件，包括只有文件头的
+        for length in [bytes.len() - 1, 40, 32, 12] {
+            fs::write(&path, &bytes[..length]).unwrap();
+            assert!(Film::load(&path).is_err(), "{length}");
+        }
+        // 宽高非常大，不能尝试分配内存
+        for (width, height) in [(u64::MAX, 2), (1 << 40, 1 << 20), (6, 3)] {
+            let mut damaged = bytes.clone();
+            damaged[8..16].copy_from_slice(&u64::to_le_bytes(width));
+            damaged[16..24].copy_from_slice(&u64::to_le_bytes(height));
+            fs::write(&path, &damaged).unwrap();
+            assert!(Film::load(&path).is_err(), "{width}x{height}");
+        }
+        // 是否有辅助信息只能是 0 或者 1
+        let mut damaged = bytes.clone();
+        damaged[32] = 2;
+        fs::write(&path, &damaged).unwrap();
+        assert!(Film::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,775 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::color::{self, Display};
+use crate::deflate;
+use crate::film::Film;
+use crate::image::{Format, Image};
+use crate::vector3::Color;
+
+// OpenEXR 的压缩方式
+#[derive(Clone, Copy)]
+pub enum Compression {
+    None,
+    // zlib 压缩，每 16 行一块
+    Zip,
+}
+
+impl Compression {
+    // 在文件头里的编号和每一块的行数
+    fn code(self) -> (u8, usize) {
+        match self {
+            Compression::None => (0, 1),
+            Compression::Zip => (3, 16),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zip" => Ok(Compression::Zip),
+            _ => Err(format!("unknown EXR compression: {s}")),
+        }
+    }
+}
+
+// 浮点的 RGB 图片，像素从上到下、从左到右排列，颜色是线性的
+pub struct HdrImage {
+    pub width: u64,
+    pub height: u64,
+    pub pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    /// 累加缓冲区里每个像素的平均颜色，不做任何截断
+    pub fn new(film: &Film) -> HdrImage {
+        let pixels = film
+            .colors
+            .iter()
+            .zip(&film.counts)
+            .map(|(&color, &count)| color / count.max(1) as f64)
+            .collect();
+        HdrImage {
+            width: film.width,
+            height: film.height,
+            pixels,
+        }
+    }
+
+    /// 每个像素实际的采样数，三个通道相同
+    pub fn sample_counts(film: &Film) -> HdrImage {
+        let pixels = film
+            .counts
+            .iter()
+            .map(|&count| Color::new(count as f64, count as f64, count as f64))
+            .collect();
+        HdrImage {
+            width: film.width,
+            height: film.height,
+            pixels,
+        }
+    }
+
+    /// 把 8 位的 sRGB 图片转换成线性的颜色，只用于本来就是 8 位的图片
+    pub fn from_image(image: &Image) -> HdrImage {
+        let pixels = image
+            .data
+            .chunks(3)
+            .map(|rgb| {
+                Color::new(
+                    color::linear(rgb[0]),
+                    color::linear(rgb[1]),
+                    color::linear(rgb[2]),
+                )
+            })
+            .collect();
+        HdrImage {
+            width: image.width,
+            height: image.height,
+            pixels,
+        }
+    }
+
+    /// 读取 Radiance 的 .hdr（RGBE）、PFM 或者 OpenEXR 图片
+    pub fn load(path: &Path) -> Result<HdrImage, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let result = match data.get(..4) {
+            Some([b'#', b'?', ..]) => decode_rgbe(&data),
+            Some([b'P', b'F' | b'f', ..]) => decode_pfm(&data),
+            Some([0x76, 0x2f, 0x31, 0x01]) => decode_exr(&data),
+            _ => Err(String::from("unsupported HDR image format")),
+        };
+        result.map_err(|message| format!("{}: {message}", path.display()))
+    }
+
+    /// 8 位的格式按照默认的方式（截断、sRGB）转换
+    pub fn write(&self, format: Format, out: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Pfm => self.write_pfm(out),
+            Format::Exr(compression) => self.write_exr(compression, out),
+            Format::Ppm | Format::Png => {
+                Image::from_hdr(self, &Display::default()).write(format, out)
+            }
+        }
+    }
+
+    // 每个像素 3 个 32 位浮点数，比例是负数表示小端，行从下到上排列
+    fn write_pfm(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let width = self.width as usize;
+        let mut data = Vec::with_capacity(self.pixels.len() * 12);
+        for row in self.pixels.chunks(width.max(1)).rev() {
+            for pixel in row {
+                for value in [pixel.x, pixel.y, pixel.z] {
+                    data.extend((value as f32).to_le_bytes());
+                }
+            }
+        }
+        out.write_all(&data)
+    }
+
+    // 单个部分、按行存储的 OpenEXR，R、G、B 三个 32 位浮点通道
+    fn write_exr(&self, compression: Compression, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (code, lines) = compression.code();
+
+        let mut header = EXR_MAGIC.to_vec();
+        header.extend([2, 0, 0, 0]);
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            for text in [name, kind] {
+                header.extend(text.as_bytes());
+                header.push(0);
+            }
+            header.extend((value.len() as i32).to_le_bytes());
+            header.extend(value);
+        };
+        // 通道按照名字排序，每个通道是名字、类型（2 是 32 位浮点数）、pLinear 和保留的 3 个字节、x 和 y 方向的采样间隔
+        let mut channels = Vec::new();
+        for name in [b'B', b'G', b'R'] {
+            channels.extend([name, 0]);
+            for value in [2i32, 0, 1, 1] {
+                channels.extend(value.to_le_bytes());
+            }
+        }
+        channels.push(0);
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        attribute("channels", "chlist", &channels);
+        attribute("compression", "compression", &[code]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        // 每一块是第一行的序号、数据的长度和数据，数据里每一行按照通道的顺序存储
+        let chunks: Vec<Vec<u8>> = (0..height)
+            .step_by(lines)
+            .map(|y| {
+                let mut raw = Vec::new();
+                for row in y..(y + lines).min(height) {
+                    let pixels = &self.pixels[row * width..(row + 1) * width];
+                    for channel in [2, 1, 0] {
+                        for pixel in pixels {
+                            raw.extend((pixel[channel] as f32).to_le_bytes());
+                        }
+                    }
+                }
+                // 压缩之后没有变小的块直接存储
+                let data = match compression {
+                    Compression::Zip => Some(zip(&raw)).filter(|data| data.len() < raw.len()),
+                    Compression::None => None,
+                };
+                let data = data.unwrap_or(raw);
+                let mut chunk = (y as i32).to_le_bytes().to_vec();
+                chunk.extend((data.len() as i32).to_le_bytes());
+                chunk.extend(data);
+                chunk
+            })
+            .collect();
+
+        // 文件头之后是每一块的位置
+        out.write_all(&header)?;
+        let mut offset = header.len() + 8 * chunks.len();
+        for chunk in &chunks {
+            out.write_all(&(offset as u64).to_le_bytes())?;
+            offset += chunk.len();
+        }
+        for chunk in &chunks {
+            out.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+// zlib 最多把数据压缩到大约 1/1032，解压之后比这更大的块一定是损坏的
+const MAX_ZIP_RATIO: usize = 1032;
+
+// EXR 的 ZIP 压缩先把偶数位置和奇数位置的字节分开，再存储相邻字节的差，最后用 zlib 压缩
+fn zip(raw: &[u8]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = raw
+        .iter()
+        .step_by(2)
+        .chain(raw.iter().skip(1).step_by(2))
+        .copied()
+        .collect();
+    for i in (1..bytes.len()).rev() {
+        bytes[i] = bytes[i].wrapping_sub(bytes[i - 1]).wrapping_add(128);
+    }
+    deflate::zlib(&bytes)
+}
+
+fn unzip(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = deflate::unzlib(data)?;
+    if bytes.len() != size {
+        return Err(String::from("invalid EXR block size"));
+    }
+    for i in 1..bytes.len() {
+        bytes[i] = bytes[i - 1].wrapping_add(bytes[i]).wrapping_sub(128);
+    }
+    let (even, odd) = bytes.split_at(size.div_ceil(2));
+    let mut raw = Vec::with_capacity(size);
+    for i in 0..size {
+        raw.push(if i % 2 == 0 { even[i / 2] } else { odd[i / 2] });
+    }
+    Ok(raw)
+}
+
+// 通道的名字和每个值的字节数（16 位或者 32 位浮点数）
+struct Channel {
+    name: String,
+    half: bool,
+}
+
+fn decode_exr(data: &[u8]) -> Result<HdrImage, String> {
+    let too_short = || String::from("EXR data is too short");
+    let flags = data.get(4..8).ok_or_else(too_short)?;
+    // 只支持第 2 版的单个部分、按行存储的文件，标志位 9、11、12 分别表示分块存储、深度数据和多个部分
+    if flags[0] != 2 || flags[1] & 0x1a != 0 {
+        return Err(String::from(
+            "only single-part scanline EXR files are supported",
+        ));
+    }
+
+    let mut position = 8;
+    let string = |position: &mut usize| {
+        let rest = data.get(*position..).unwrap_or_default();
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(too_short)?;
+        *position += end + 1;
+        Ok::<_, String>(String::from_utf8_lossy(&rest[..end]).into_owned())
+    };
+    let int =
+        |bytes: &[u8], i: usize| i32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+
+    let (mut channels, mut compression, mut window) = (Vec::new(), None, None);
+    loop {
+        let name = string(&mut position)?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = string(&mut position)?;
+        let size = data.get(position..position + 4).ok_or_else(too_short)?;
+        let size = usize::try_from(int(size, 0)).map_err(|_| "invalid EXR attribute size")?;
+        let value = data
+            .get(position + 4..position + 4 + size)
+            .ok_or_else(too_short)?;
+        position += 4 + size;
+
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => {
+                let mut i = 0;
+                while value.get(i).is_some_and(|&byte| byte != 0) {
+                    let end = i + value[i..]
+                        .iter()
+                        .position(|&byte| byte == 0)
+                        .ok_or_else(too_short)?;
+                    let name = String::from_utf8_lossy(&value[i..end]).into_owned();
+                    let kind = value.get(end + 1..end + 17).ok_or_else(too_short)?;
+                    let half = match int(kind, 0) {
+                        1 => true,
+                        2 => false,
+                        _ => return Err(format!("unsupported EXR pixel type in channel {name}")),
+                    };
+                    if int(kind, 2) != 1 || int(kind, 3) != 1 {
+                        return Err(String::from("subsampled EXR channels are not supported"));
+                    }
+                    channels.push(Channel { name, half });
+                    i = end + 17;
+                }
+            }
+            ("compression", "compression") => compression = value.first().copied(),
+            ("dataWindow", "box2i") if value.len() == 16 => {
+                window = Some([0, 1, 2, 3].map(|i| int(value, i)));
+            }
+            _ => {}
+        }
+    }
+
+    let [x0, y0, x1, y1] = window.ok_or("missing EXR data window")?;
+    // 用 i64 相减，损坏的文件里的坐标相减可能溢出
+    let length = |min: i32, max: i32| usize::try_from(max as i64 - min as i64 + 1).ok();
+    let (width, height) = match (length(x0, x1), length(y0, y1)) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(String::from("invalid EXR data window")),
+    };
+    let (compression, lines) = match compression {
+        Some(0) => (Compression::None, 1),
+        // ZIPS 每一块只有一行
+        Some(2) => (Compression::Zip, 1),
+        Some(3) => (Compression::Zip, 16),
+        _ => return Err(String::from("unsupported EXR compression")),
+    };
+    // R、G、B 三个通道，只有一个 Y 通道的是灰度图
+    let find = |names: &[&str]| {
+        channels
+            .iter()
+            .position(|channel| names.contains(&channel.name.as_str()))
+    };
+    let rgb = match (find(&["R"]), find(&["G"]), find(&["B"]), find(&["Y"])) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y; 3],
+        _ => return Err(String::from("EXR image has no RGB or Y channels")),
+    };
+
+    let too_large = || String::from("EXR image is too large");
+    let bytes_per_line = channels.iter().try_fold(0usize, |sum, channel| {
+        let bytes = if channel.half { 2 } else { 4 };
+        width
+            .checked_mul(bytes)
+            .and_then(|bytes| sum.checked_add(bytes))
+            .ok_or_else(too_large)
+    })?;
+
+    // 先检查每一块的位置和大小，都合理之后再分配像素的内存
+    let count = height.div_ceil(lines);
+    let table = count
+        .checked_mul(8)
+        .and_then(|bytes| data.get(position..position.checked_add(bytes)?))
+        .ok_or_else(too_short)?;
+    let mut blocks = Vec::with_capacity(count);
+    for offset in table.chunks(8) {
+        let offset = u64::from_le_bytes(offset.try_into().unwrap());
+        let offset = usize::try_from(offset).map_err(|_| too_short())?;
+        let header = offset
+            .checked_add(8)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(too_short)?;
+        let y = usize::try_from(int(header, 0) as i64 - y0 as i64)
+            .ok()
+            .filter(|&y| y < height)
+            .ok_or("invalid EXR block position")?;
+        let size = usize::try_from(int(header, 1)).map_err(|_| "invalid EXR block size")?;
+        let block = data
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(too_short)?;
+
+        let rows = lines.min(height - y);
+        let expected = rows.checked_mul(bytes_per_line).ok_or_else(too_large)?;
+        let valid = match compression {
+            Compression::Zip if size < expected => expected / MAX_ZIP_RATIO <= size,
+            _ => size == expected,
+        };
+        if !valid {
+            return Err(String::from("invalid EXR block size"));
+        }
+        blocks.push((y, block, expected));
+    }
+
+    let mut pixels = vec![Color::default(); width.checked_mul(height).ok_or_else(too_large)?];
+    for (y, block, expected) in blocks {
+        let raw = match block.len() < expected {
+            true => unzip(block, expected)?,
+            false => block.to_vec(),
+        };
+
+        let mut values = vec![0.0; channels.len() * width];
+        for (row, line) in raw.chunks(bytes_per_line).enumerate() {
+            let mut start = 0;
+            for (c, channel) in channels.iter().enumerate() {
+                for x in 0..width {
+                    values[c * width + x] = match channel.half {
+                        true => half(u16::from_le_bytes([
+                            line[start + 2 * x],
+                            line[start + 2 * x + 1],
+                        ])),
+                        false => f32::from_le_bytes(
+                            line[start + 4 * x..start + 4 * x + 4].try_into().unwrap(),
+                        ) as f64,
+                    };
+                }
+                start += if channel.half { 2 } else { 4 } * width;
+            }
+            for x in 0..width {
+                let [r, g, b] = rgb.map(|c| values[c * width + x]);
+                pixels[(y + row) * width + x] = Color::new(r, g, b);
+            }
+        }
+    }
+
+    Ok(HdrImage {
+        width: width as u64,
+        height: height as u64,
+        pixels,
+    })
+}
+
+// 16 位的半精度浮点数
+fn half(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = (bits >> 10 & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+// 头部是 PF（RGB）或者 Pf（灰度）、宽、高和比例，比例的符号表示字节序，之后只有一个空白字符
+fn decode_pfm(data: &[u8]) -> Result<HdrImage, String> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while tokens.len() < 4 {
+        while data.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        let start = i;
+        while data.get(i).is_some_and(|c| !c.is_ascii_whitespace()) {
+            i += 1;
+        }
+        if start == i {
+            return Err(String::from("invalid PFM header"));
+        }
+        tokens.push(String::from_utf8_lossy(&data[start..i]).into_owned());
+    }
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(String::from("invalid PFM header")),
+    };
+    let (Ok(width @ 1..), Ok(height @ 1..), Ok(scale)) = (
+        tokens[1].parse::<usize>(),
+        tokens[2].parse::<usize>(),
+        tokens[3].parse::<f64>(),
+    ) else {
+        return Err(String::from("invalid PFM header"));
+    };
+
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels * 4))
+        .ok_or("PFM image is too large")?;
+    let values = (i + 1)
+        .checked_add(size)
+        .and_then(|end| data.get(i + 1..end))
+        .ok_or("PFM data is too short")?;
+    let value = |i: usize| {
+        let bytes = values[i * 4..i * 4 + 4].try_into().unwrap();
+        match scale < 0.0 {
+            true => f32::from_le_bytes(bytes) as f64,
+            false => f32::from_be_bytes(bytes) as f64,
+        }
+    };
+
+    // 行从下到上排列
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in (0..height).rev() {
+        for x in 0..width {
+            let i = (row * width + x) * channels;
+            pixels.push(match channels {
+                3 => Color::new(value(i), value(i + 1), value(i + 2)),
+                _ => Color::new(value(i), value(i), value(i)),
+            });
+        }
+    }
+    Ok(HdrImage {
+        width: width as u64,
+        height: height as u64,
+        pixels,
+    })
+}
+
+// 头部是若干行文本，以空行结束，之后一行是分辨率，然后是每一行的像素
+fn decode_rgbe(data: &[u8]) -> Result<HdrImage, String> {
+    if !data.starts_with(b"#?") {
+        return Err(String::from("not a Radiance HDR file"));
+    }
+
+    let mut lines = data.split(|&byte| byte == b'\n');
+    let mut offset = 0;
+    let mut line = |offset: &mut usize| {
+        let line = lines.next()?;
+        *offset += line.len() + 1;
+        Some(String::from_utf8_lossy(line).trim().to_string())
+    };
+    loop {
+        let header = line(&mut offset).ok_or("HDR header is not terminated")?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported HDR format {format}"));
+            }
+        }
+    }
+
+    // 只支持最常见的方向：从上到下、从左到右
+    let resolution = line(&mut offset).ok_or("missing HDR resolution")?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<u64>(), width.parse::<u64>()),
+        _ => return Err(format!("unsupported HDR resolution {resolution}")),
+    };
+    let (Ok(height @ 1..), Ok(width @ 1..)) = (height, width) else {
+        return Err(format!("invalid HDR resolution {resolution}"));
+    };
+
+    let mut rest = data.get(offset..).unwrap_or_default();
+    // 分配内存之前先按照数据的长度检查宽高：游程编码的一行每个通道每 127 个像素至少两个字节，
+    // 没有编码的一行更长
+    let shortest = width
+        .div_ceil(127)
+        .checked_mul(8)
+        .and_then(|bytes| bytes.checked_mul(height));
+    if shortest.is_none_or(|bytes| bytes > rest.len() as u64) {
+        return Err(String::from("HDR data is too short"));
+    }
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for _ in 0..height {
+        rest = read_scanline(rest, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| color(rgbe)));
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// 读取一行像素，返回剩下的数据。宽度在 8 到 32767 之间的行通常按照通道分别做了游程编码，
+// 以 2、2 和两个字节的宽度开头，否则每个像素直接存储 4 个字节
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Result<&'a [u8], String> {
+    let width = scanline.len();
+    let too_short = || String::from("HDR data is too short");
+    let encoded = match data {
+        [2, 2, high, low, ..] if (8..0x8000).contains(&width) => {
+            (*high as usize) << 8 | *low as usize == width
+        }
+        _ => false,
+    };
+
+    if !encoded {
+        let bytes = data.get(..width * 4).ok_or_else(too_short)?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(bytes.chunks(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[width * 4..]);
+    }
+
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().ok_or_else(too_short)?;
+            // 大于 128 表示重复下一个字节，否则后面跟着 count 个字节
+            let (count, run) = match count > 128 {
+                true => ((count - 128) as usize, true),
+                false => (count as usize, false),
+            };
+            if count == 0 || x + count > width {
+                return Err(String::from("invalid HDR run length"));
+            }
+            let length = if run { 1 } else { count };
+            let values = rest.get(..length).ok_or_else(too_short)?;
+            for (i, pixel) in scanline[x..x + count].iter_mut().enumerate() {
+                pixel[channel] = values[if run { 0 } else { i }];
+            }
+            x += count;
+            data = &rest[length..];
+        }
+    }
+    Ok(data)
+}
+
+// 三个颜色共用一个指数
+fn color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::default();
+    }
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    Color::new(
+        (r as f64 + 0.5) * scale,
+        (g as f64 + 0.5) * scale,
+        (b as f64 + 0.5) * scale,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_flat_and_run_length_scanlines() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n";
+        let mut data = header.to_vec();
+        // 第一行按照通道做游程编码：红色 8 个 127，绿色前 4 个 0 后 4 个 255，蓝色逐个给出，指数都是 129
+        data.extend([2, 2, 0, 8]);
+        data.extend([128 + 8, 127]);
+        data.extend([128 + 4, 0, 128 + 4, 255]);
+        data.extend([8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        data.extend([128 + 8, 129]);
+        // 第二行没有编码
+        for x in 0..8 {
+            data.extend([x * 16, 0, 0, 128]);
+        }
+
+        let image = decode_rgbe(&data).unwrap();
+        assert_eq!((image.width, image.height), (8, 2));
+        let first = image.pixels[0];
+        assert!((first.x - 127.5 / 128.0).abs() < 1e-12);
+        assert_eq!(first.y, 0.5 / 128.0);
+        assert!((image.pixels[5].y - 255.5 / 128.0).abs() < 1e-12);
+        assert!((image.pixels[3].z - 3.5 / 128.0).abs() < 1e-12);
+        assert_eq!(image.pixels[8].x, 0.5 / 256.0);
+        assert!((image.pixels[15].x - 112.5 / 256.0).abs() < 1e-12);
+    }
+
+    // 包括很大、很小和负数的值，都能用 32 位浮点数精确表示
+    fn gradient() -> HdrImage {
+        let (width, height) = (37, 21);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                Color::new(x * 0.25, 1000.0 * y, -0.5 + (x * y) / 1024.0)
+            })
+            .collect();
+        HdrImage {
+            width: width as u64,
+            height: height as u64,
+            pixels,
+        }
+    }
+
+    fn round_trip(format: Format) -> HdrImage {
+        let mut data = Vec::new();
+        gradient().write(format, &mut data).unwrap();
+        match format {
+            Format::Pfm => decode_pfm(&data).unwrap(),
+            _ => decode_exr(&data).unwrap(),
+        }
+    }
+
+    #[test]
+    fn float_formats_round_trip() {
+        let expected = gradient();
+        for format in [
+            Format::Pfm,
+            Format::Exr(Compression::None),
+            Format::Exr(Compression::Zip),
+        ] {
+            let image = round_trip(format);
+            assert_eq!(
+                (image.width, image.height),
+                (expected.width, expected.height)
+            );
+            for (a, b) in image.pixels.iter().zip(&expected.pixels) {
+                assert_eq!([a.x, a.y, a.z], [b.x, b.y, b.z]);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_half_floats() {
+        assert_eq!(half(0x3c00), 1.0);
+        assert_eq!(half(0xc000), -2.0);
+        assert_eq!(half(0x7bff), 65504.0);
+        assert_eq!(half(0x0001), 2f64.powi(-24));
+        assert_eq!(half(0x7c00), f64::INFINITY);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let image = HdrImage {
+            width: 5,
+            height: 3,
+            pixels: vec![Color::new(1.0, 2.0, 3.0); 15],
+        };
+        let encode = |format| {
+            let mut data = Vec::new();
+            image.write(format, &mut data).unwrap();
+            data
+        };
+
+        // 截断在任何位置都返回错误，不会越界
+        for compression in [Compression::None, Compression::Zip] {
+            let data = encode(Format::Exr(compression));
+            assert!(decode_exr(&data).is_ok());
+            for length in 0..data.len() {
+                assert!(decode_exr(&data[..length]).is_err(), "{length}");
+            }
+        }
+        let pfm = encode(Format::Pfm);
+        for length in 0..pfm.len() {
+            assert!(decode_pfm(&pfm[..length]).is_err(), "{length}");
+        }
+
+        let exr = encode(Format::Exr(Compression::None));
+        let find = |text: &[u8]| {
+            let start = exr.windows(text.len()).position(|w| w == text).unwrap();
+            start + text.len()
+        };
+        let damaged = |position: usize, bytes: &[u8]| {
+            let mut data = exr.clone();
+            data[position..position + bytes.len()].copy_from_slice(bytes);
+            decode_exr(&data)
+        };
+        // 分块存储、深度数据和多个部分的文件，长名字是支持的
+        for flag in [0x02, 0x08, 0x10] {
+            assert!(damaged(5, &[flag]).is_err());
+        }
+        assert!(damaged(5, &[0x04]).is_ok());
+        // 负数的属性长度
+        assert!(damaged(find(b"chlist\0"), &(-1i32).to_le_bytes()).is_err());
+        // 相减会溢出的数据窗口
+        let window = find(b"dataWindow\0box2i\0") + 4;
+        assert!(damaged(window, &i32::MIN.to_le_bytes()).is_err());
+        assert!(damaged(window + 8, &i32::MAX.to_le_bytes()).is_err());
+        // 负数的块长度和超出文件的块位置
+        let table = exr.len() - 3 * (8 + 5 * 12) - 3 * 8;
+        assert!(damaged(table + 8, &u64::MAX.to_le_bytes()).is_err());
+        let block = u64::from_le_bytes(exr[table..table + 8].try_into().unwrap()) as usize;
+        assert_eq!(block, table + 3 * 8);
+        assert!(damaged(block + 4, &(-60i32).to_le_bytes()).is_err());
+        assert!(damaged(block, &i32::MAX.to_le_bytes()).is_err());
+
+        // 空的和乘法溢出的 PFM 头部
+        assert!(decode_pfm(b"PF\n0 0\n-1.0\n").is_err());
+        assert!(decode_pfm(b"Pf\n3 0\n-1.0\n").is_err());
+        let huge = b"PF\n4294967296 4294967296\n-1.0\n\0\0\0\0";
+        assert!(decode_pfm(huge).is_err());
+        let huge = b"PF\n18446744073709551615 2\n-1.0\n\0\0\0\0";
+        assert!(decode_pfm(huge).is_err());
+        // 宽高和数据的长度不符的 RGBE 文件
+        let huge = b"#?RADIANCE\n\n-Y 4294967296 +X 4294967296\n\x02\x02";
+        assert!(decode_rgbe(huge).is_err());
+        assert!(decode_rgbe(b"#?RADIANCE\n\n-Y 0 +X 8\n").is_err());
+    }
+}
@@ -1,21 +1,88 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats;
 use crate::vector3::{Point3, Vector3};
 
-pub trait Hit {
+pub trait Hit: Send + Sync {
     fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord>;
+
+    /// 物体的包围盒，无限大的物体返回 None
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// 从 origin 朝着物体随机采样一个方向，用来对光源采样
+    fn random_direction(&self, _origin: Point3, _sampler: &mut Sampler) -> Vector3 {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+
+    /// random_direction 采样到 direction 方向的概率密度（立体角），不能采样的物体是 0
+    fn pdf(&self, _origin: Point3, _direction: Vector3) -> f64 {
+        0.0
+    }
 }
 
 pub struct HitRecord {
     pub point: Point3,
     pub normal: Vector3,
     pub t: f64,
-    pub material: Rc<dyn Material>,
+    // 表面的纹理坐标
+    pub u: f64,
+    pub v: f64,
+    pub material: Arc<dyn Material>,
     pub face: bool,
+    // 场景里的第几个物体，从 1 开始，没有编号的是 0
+    pub object: usize,
+}
+
+// 共享的物体，比如同一个网格的多个实例
+impl<T: Hit + ?Sized> Hit for Arc<T> {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        (**self).hit(ray, min, max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        (**self).random_direction(origin, sampler)
+    }
+
+    fn pdf(&self, origin: Point3, direction: Vector3) -> f64 {
+        (**self).pdf(origin, direction)
+    }
 }
 
+// 给物体编号，命中的时候记录在 HitRecord 里
+pub struct Numbered {
+    object: Box<dyn Hit>,
+    index: usize,
+}
+
+impl Numbered {
+    pub fn new(object: Box<dyn Hit>, index: usize) -> Numbered {
+        Numbered { object, index }
+    }
+}
+
+impl Hit for Numbered {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        let mut record = self.object.hit(ray, min, max)?;
+        record.object = self.index;
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
+
+// 场景里可以采样的光源
+pub type Lights = Vec<Arc<dyn Hit>>;
+
 pub type World = Vec<Box<dyn Hit>>;
 
 impl Hit for World {
@@ -24,6 +91,7 @@ impl Hit for World {
         let mut nearest = max;
 
         // 找到一个最近的
+        stats::count_tests(self.len() as u64);
         for hittable in self {
             if let Some(record) = hittable.hit(ray, min, nearest) {
                 nearest = record.t;
@@ -33,4 +101,10 @@ impl Hit for World {
 
         result
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.iter().map(|hittable| hittable.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |result, bbox| Some(result.union(bbox?)))
+    }
 }
@@ -0,0 +1,508 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::color::Display;
+use crate::deflate;
+use crate::film::Film;
+use crate::hdr::{Compression, HdrImage};
+
+// 输出的图片格式
+#[derive(Clone, Copy)]
+pub enum Format {
+    // 二进制的 PPM（P6）
+    Ppm,
+    Png,
+    // 下面两种保存没有截断的线性颜色
+    Pfm,
+    Exr(Compression),
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Ppm => "ppm",
+            Format::Png => "png",
+            Format::Pfm => "pfm",
+            Format::Exr(_) => "exr",
+        }
+    }
+
+    /// 高动态范围的格式
+    pub fn is_hdr(self) -> bool {
+        matches!(self, Format::Pfm | Format::Exr(_))
+    }
+
+    /// 根据文件的扩展名选择格式
+    pub fn from_path(path: &str) -> Option<Format> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ppm" => Ok(Format::Ppm),
+            "png" => Ok(Format::Png),
+            "pfm" => Ok(Format::Pfm),
+            "exr" => Ok(Format::Exr(Compression::Zip)),
+            _ => Err(format!("unknown output format: {s}")),
+        }
+    }
+}
+
+// 8 位 RGB 图片，像素从上到下、从左到右排列
+pub struct Image {
+    pub width: u64,
+    pub height: u64,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// 用累加缓冲区里每个像素的平均颜色生成图片
+    pub fn new(film: &Film, display: &Display) -> Image {
+        Image::from_hdr(&HdrImage::new(film), display)
+    }
+
+    pub fn from_hdr(image: &HdrImage, display: &Display) -> Image {
+        let data = image
+            .pixels
+            .iter()
+            .flat_map(|&color| display.encode(color))
+            .collect();
+        Image {
+            width: image.width,
+            height: image.height,
+            data,
+        }
+    }
+
+    /// 每个像素的采样数的热力图，从蓝色（很少）经过绿色、黄色到红色（max 次）
+    pub fn heatmap(film: &Film, max: u64) -> Image {
+        const STOPS: [[f64; 3]; 5] = [
+            [0.0, 0.0, 0.5],
+            [0.0, 0.4, 1.0],
+            [0.0, 0.8, 0.3],
+            [1.0, 0.9, 0.0],
+            [0.9, 0.1, 0.0],
+        ];
+        let data = film
+            .counts
+            .iter()
+            .flat_map(|&count| {
+                let t = (count as f64 / max.max(1) as f64).clamp(0.0, 1.0);
+                let position = t * (STOPS.len() - 1) as f64;
+                let index = (position as usize).min(STOPS.len() - 2);
+                let fraction = position - index as f64;
+                let (a, b) = (STOPS[index], STOPS[index + 1]);
+                [0, 1, 2].map(|c| ((a[c] + fraction * (b[c] - a[c])) * 255.0).round() as u8)
+            })
+            .collect();
+        Image {
+            width: film.width,
+            height: film.height,
+            data,
+        }
+    }
+
+    /// 读取 PNG 或者 PPM（P3、P6）图片，根据文件的内容判断格式
+    pub fn load(path: &Path) -> Result<Image, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let result = match data.get(..2) {
+            Some(b"\x89P") => decode_png(&data),
+            Some(b"P3" | b"P6") => decode_ppm(&data),
+            _ => Err(String::from("unsupported image format")),
+        };
+        result.map_err(|message| format!("{}: {message}", path.display()))
+    }
+
+    pub fn write(&self, format: Format, out: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Ppm => self.write_ppm(out),
+            Format::Png => self.write_png(out),
+            Format::Pfm | Format::Exr(_) => HdrImage::from_image(self).write(format, out),
+        }
+    }
+
+    fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        // 第一行输出 P6，表示二进制的像素图，然后是宽和高，和最大颜色值
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.data)
+    }
+
+    fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 位深 8，颜色类型 2（RGB），压缩、过滤和隔行扫描方式都是 0
+        header.extend([8, 2, 0, 0, 0]);
+        chunk(out, b"IHDR", &header)?;
+
+        chunk(out, b"IDAT", &deflate::zlib(&self.filter()))?;
+        chunk(out, b"IEND", &[])
+    }
+
+    // 每一行选择一种让差值最小的过滤方式，过滤后的数据更容易压缩
+    fn filter(&self) -> Vec<u8> {
+        let stride = self.width as usize * 3;
+        let mut result = Vec::with_capacity((stride + 1) * self.height as usize);
+        let empty = vec![0; stride];
+
+        for (index, line) in self.data.chunks(stride).enumerate() {
+            let above = match index {
+                0 => &empty[..],
+                _ => &self.data[(index - 1) * stride..index * stride],
+            };
+
+            let (kind, filtered) = (0..5)
+                .map(|kind| (kind, filter_line(kind, line, above)))
+                .min_by_key(|(_, line)| {
+                    let cost = |&b: &u8| (b as i8).unsigned_abs() as u64;
+                    line.iter().map(cost).sum::<u64>()
+                })
+                .unwrap();
+            result.push(kind);
+            result.extend(filtered);
+        }
+        result
+    }
+}
+
+// PNG 的五种过滤方式：None、Sub、Up、Average、Paeth
+fn filter_line(kind: u8, line: &[u8], above: &[u8]) -> Vec<u8> {
+    (0..line.len())
+        .map(|i| {
+            let a = if i >= 3 { line[i - 3] } else { 0 };
+            let b = above[i];
+            let c = if i >= 3 { above[i - 3] } else { 0 };
+            let predictor = match kind {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            line[i].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// PNG 的数据块：长度、类型、数据和 CRC
+fn chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32(crc32(!0, kind), data);
+    out.write_all(&(!crc).to_be_bytes())
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    crc
+}
+
+// 把 PPM 头部按照空白分开，`#` 开始的是注释
+fn ppm_tokens(data: &[u8], count: usize) -> Result<(Vec<u64>, usize), String> {
+    let mut values = Vec::new();
+    let mut i = 2;
+    while values.len() < count {
+        match data.get(i) {
+            Some(b'#') => {
+                while data.get(i).is_some_and(|&c| c != b'\n') {
+                    i += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => i += 1,
+            Some(c) if c.is_ascii_digit() => {
+                let start = i;
+                while data.get(i).is_some_and(u8::is_ascii_digit) {
+                    i += 1;
+                }
+                let text = std::str::from_utf8(&data[start..i]).unwrap();
+                values.push(text.parse().map_err(|_| "invalid PPM number")?);
+            }
+            _ => return Err(String::from("invalid PPM header")),
+        }
+    }
+    Ok((values, i))
+}
+
+fn decode_ppm(data: &[u8]) -> Result<Image, String> {
+    let (header, end) = ppm_tokens(data, 3)?;
+    let (width, height, max) = (header[0], header[1], header[2]);
+    if max == 0 || max > 65535 {
+        return Err(format!("invalid PPM max value {max}"));
+    }
+    // 损坏的文件里的宽高可能非常大，乘法溢出的时候返回错误
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or("PPM image is too large")?;
+    let scale = |value: u64| (value * 255 / max) as u8;
+
+    let data = match &data[..2] {
+        b"P3" => {
+            let count = size.checked_add(3).ok_or("PPM image is too large")?;
+            let (values, _) = ppm_tokens(data, count)?;
+            values[3..]
+                .iter()
+                .map(|&value| scale(value.min(max)))
+                .collect()
+        }
+        _ => {
+            // 头部之后只有一个空白字符
+            let pixels = data.get(end + 1..).unwrap_or_default();
+            let bytes = if max < 256 { 1 } else { 2 };
+            if size.checked_mul(bytes).is_none_or(|n| pixels.len() < n) {
+                return Err(String::from("PPM data is too short"));
+            }
+            match bytes {
+                1 => pixels[..size].iter().map(|&v| scale(v as u64)).collect(),
+                _ => pixels[..size * 2]
+                    .chunks(2)
+                    .map(|v| scale(u16::from_be_bytes([v[0], v[1]]) as u64))
+                    .collect(),
+            }
+        }
+    };
+    Ok(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+// 支持非隔行扫描的灰度、RGB、调色板、带透明度的 PNG，透明度会被忽略
+fn decode_png(data: &[u8]) -> Result<Image, String> {
+    if data.get(..8) != Some(b"\x89PNG\r\n\x1a\n") {
+        return Err(String::from("invalid PNG signature"));
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut i = 8;
+    while i + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[i..i + 4].try_into().unwrap()) as usize;
+        let kind = &data[i + 4..i + 8];
+        let Some(content) = data.get(i + 8..i + 8 + length) else {
+            return Err(String::from("truncated PNG chunk"));
+        };
+        match kind {
+            b"IHDR" if length == 13 => header = Some(content.to_vec()),
+            b"PLTE" => palette = content.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(content),
+            b"IEND" => break,
+            _ => {}
+        }
+        // 跳过 CRC
+        i += 12 + length;
+    }
+
+    let header = header.ok_or("missing PNG header")?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as u64;
+    let (depth, color, interlace) = (header[8], header[9], header[12]);
+    if interlace != 0 {
+        return Err(String::from("interlaced PNG is not supported"));
+    }
+
+    // 每个像素的通道数
+    let channels = match color {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("invalid PNG color type {color}")),
+    };
+    if !(depth == 8 || depth == 16 && color != 3) {
+        return Err(format!("unsupported PNG bit depth {depth}"));
+    }
+
+    // 宽高最大是 2^32 - 1，乘法溢出的时候在解压和分配内存之前返回错误
+    let bytes = channels * depth as usize / 8;
+    let too_large = || format!("PNG image {width}x{height} is too large");
+    let stride = usize::try_from(width)
+        .ok()
+        .and_then(|width| width.checked_mul(bytes))
+        .ok_or_else(too_large)?;
+    let size = usize::try_from(height)
+        .ok()
+        .and_then(|height| stride.checked_add(1)?.checked_mul(height))
+        .ok_or_else(too_large)?;
+    let raw = deflate::unzlib(&compressed)?;
+    if raw.len() < size {
+        return Err(String::from("PNG data is too short"));
+    }
+
+    // 反向过滤，恢复原始的像素数据
+    let mut pixels = vec![0u8; stride * height as usize];
+    for row in 0..height as usize {
+        let line = &raw[row * (stride + 1)..(row + 1) * (stride + 1)];
+        let (kind, line) = (line[0], &line[1..]);
+        for x in 0..stride {
+            let a = if x >= bytes {
+                pixels[row * stride + x - bytes]
+            } else {
+                0
+            };
+            let b = if row > 0 {
+                pixels[(row - 1) * stride + x]
+            } else {
+                0
+            };
+            let c = if row > 0 && x >= bytes {
+                pixels[(row - 1) * stride + x - bytes]
+            } else {
+                0
+            };
+            let predictor = match kind {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("invalid PNG filter {kind}")),
+            };
+            pixels[row * stride + x] = line[x].wrapping_add(predictor);
+        }
+    }
+
+    // 16 位的通道只取高 8 位
+    let step = depth as usize / 8;
+    let sample = |pixel: &[u8], channel: usize| pixel[channel * step];
+    let mut data = Vec::with_capacity((width * height * 3) as usize);
+    for pixel in pixels.chunks(bytes) {
+        match color {
+            0 | 4 => data.extend([sample(pixel, 0); 3]),
+            3 => {
+                let index = pixel[0] as usize * 3;
+                let Some(rgb) = palette.get(index..index + 3) else {
+                    return Err(String::from("PNG palette index out of range"));
+                };
+                data.extend_from_slice(rgb);
+            }
+            _ => data.extend([sample(pixel, 0), sample(pixel, 1), sample(pixel, 2)]),
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> Image {
+        let (width, height) = (37u64, 11u64);
+        let data = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 7) as u8, (y * 23) as u8, ((x * y) % 256) as u8]
+            })
+            .collect();
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    #[test]
+    fn crc_matches_known_value() {
+        assert_eq!(!crc32(!0, b"123456789"), 0xCBF4_3926);
+        // 分两次计算和一次计算的结果相同
+        assert_eq!(crc32(crc32(!0, b"1234"), b"56789"), crc32(!0, b"123456789"));
+    }
+
+    #[test]
+    fn png_and_ppm_round_trip() {
+        let image = gradient();
+        let mut png = Vec::new();
+        image.write(Format::Png, &mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let decoded = decode_png(&png).unwrap();
+        assert_eq!((decoded.width, decoded.height), (image.width, image.height));
+        assert_eq!(decoded.data, image.data);
+
+        let mut ppm = Vec::new();
+        image.write(Format::Ppm, &mut ppm).unwrap();
+        let decoded = decode_ppm(&ppm).unwrap();
+        assert_eq!((decoded.width, decoded.height), (image.width, image.height));
+        assert_eq!(decoded.data, image.data);
+    }
+
+    #[test]
+    fn malformed_ppm_is_rejected() {
+        let inputs: [&[u8]; 7] = [
+            b"P6",
+            b"P6 2 2 255",
+            b"P6 2 2 255\n\x01\x02",
+            b"P6 4294967296 4294967296 255\n",
+            b"P6 18446744073709551615 3 65535\n",
+            b"P3 2 1 255 1 2 3",
+            b"P3 18446744073709551615 1 255\n",
+        ];
+        for data in inputs {
+            let text = String::from_utf8_lossy(data);
+            assert!(decode_ppm(data).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn malformed_png_is_rejected() {
+        let mut png = Vec::new();
+        gradient().write(Format::Png, &mut png).unwrap();
+        // 截断在 IDAT 的数据里的任何位置都返回错误，不检查 CRC，只缺少 CRC 和 IEND 的可以读取
+        let end = png.len() - 16;
+        for length in 0..end {
+            assert!(decode_png(&png[..length]).is_err(), "{length}");
+        }
+        assert!(decode_png(&png[..end]).is_ok());
+        // 头部的宽高是最大值，16 位 RGBA 的一行的字节数会溢出（不检查 CRC）
+        for (width, height) in [(u32::MAX, u32::MAX), (u32::MAX, 1), (1, u32::MAX)] {
+            let mut data = png.clone();
+            data[16..20].copy_from_slice(&width.to_be_bytes());
+            data[20..24].copy_from_slice(&height.to_be_bytes());
+            data[24..26].copy_from_slice(&[16, 6]);
+            assert!(decode_png(&data).is_err(), "{width}x{height}");
+        }
+    }
+}
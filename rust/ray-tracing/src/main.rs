@@ -1,144 +1,299 @@
-use std::rc::Rc;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
-use camera::Camera;
-use hittable::{Hit, World};
-use material::{Dielectric, Lambert, Metal};
-use rand::Rng;
-use ray::Ray;
-use sphere::Sphere;
-use vector3::{Color, Point3, Vector3};
+use image::{Format, Image};
 
+use aov::Pass;
+use cli::Options;
+use color::Display;
+use film::Film;
+use hdr::HdrImage;
+use hittable::{Hit, Lights, Numbered};
+use render::{Adaptive, Checkpoint, Settings};
+use sampler::Sampler;
+
+mod aabb;
+mod animation;
+mod aov;
+mod background;
+mod bvh;
 mod camera;
+mod cli;
+mod color;
+mod deflate;
+mod denoise;
+mod environment;
+mod film;
+mod hdr;
 mod hittable;
+mod image;
 mod material;
+mod matrix;
+mod medium;
+mod mesh;
+mod noise;
+mod quad;
 mod ray;
+mod render;
+mod sampler;
+mod scene;
 mod sphere;
+mod stats;
+mod texture;
+mod toml;
+mod transform;
+mod triangle;
 mod vector3;
 
 fn main() {
-    const RATIO: f64 = 3.0 / 2.0;
-    const WIDTH: u64 = 1200;
-    const HEIGHT: u64 = ((WIDTH as f64) / RATIO) as u64;
-    const SAMPLES_PER_PIXEL: u64 = 500;
-    const MAX_DEPTH: u64 = 50;
-
-    let world = random_scene();
-
-    let camera = Camera::new(
-        Point3::new(13.0, 2.0, 3.0),
-        Point3::new(0.0, 0.0, 0.0),
-        Vector3::new(0.0, 1.0, 0.0),
-        20.0,
-        RATIO,
-        0.1,
-        10.0,
-    );
-
-    // 输出图片，第一行输出 P3，表示像素图
-    let mut content = String::from("P3");
-    // 输出宽和高，和最大颜色值
-    content.push_str(&format!("\n{WIDTH} {HEIGHT}\n255\n"));
-
-    let mut rng = rand::thread_rng();
-    for j in (0..HEIGHT).rev() {
-        // 进度
-        eprintln!("Scan lines remaining: {j}");
-        for i in 0..WIDTH {
-            let mut color = Color::default();
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let random_u: f64 = rng.gen();
-                let random_v: f64 = rng.gen();
-
-                let u = ((i as f64) + random_u) / ((WIDTH - 1) as f64);
-                let v = ((j as f64) + random_v) / ((HEIGHT - 1) as f64);
-
-                color += ray_color(&camera.get_ray(u, v), &world, MAX_DEPTH);
+    let options = Options::parse();
+
+    // 从检查点恢复时使用检查点里的种子
+    let resumed = options
+        .resume
+        .as_ref()
+        .map(|path| Film::load(path).unwrap_or_else(|error| exit(&format!("{path}: {error}"))));
+    let seed = match (&resumed, options.seed) {
+        (Some(film), Some(seed)) if film.seed != seed => exit(&format!(
+            "--seed {seed} differs from the checkpoint seed {}",
+            film.seed
+        )),
+        (Some(film), _) => film.seed,
+        (None, seed) => seed.unwrap_or_else(rand::random),
+    };
+
+    // 场景也使用同一个种子生成
+    if options.progress {
+        // 输出种子，方便重现这一次的渲染结果
+        eprintln!("Seed: {seed}");
+    }
+    let mut scene = match scene::builtin(&options.scene, &mut Sampler::new(seed)) {
+        Some(scene) => scene,
+        None => scene::load(&options.scene)
+            .unwrap_or_else(|error| exit(&format!("{}: {error}", options.scene))),
+    };
+    if let Err(message) = options.apply(&mut scene.image) {
+        exit(&message);
+    }
+    let format = options.format().unwrap_or_else(|message| exit(&message));
+    // 辅助图像保存在输出文件的旁边
+    let output = match (&options.output, options.aov.is_empty()) {
+        (None, false) => exit("--aov requires --output"),
+        (output, _) => output.clone(),
+    };
+
+    let image = &scene.image;
+    let display = image.display;
+    // 有相机动画的时候渲染第 start 到 end 帧，输出文件名加上帧号。
+    // 帧的范围可能很大，不把所有的帧号放在列表里
+    let range = match (&scene.animation, options.frames) {
+        (None, None) if !options.resume_frames => None,
+        (None, _) => exit("--frames and --resume-frames require a camera animation"),
+        (Some(_), _) if resumed.is_some() || options.checkpoint.is_some() => {
+            exit("checkpoints cannot be used with animations")
+        }
+        (Some(_), _) if output.is_none() => exit("animations require --output"),
+        (Some(animation), frames) => Some(frames.unwrap_or((animation.start, animation.end))),
+    };
+    let frames: Box<dyn Iterator<Item = Option<u64>>> = match range {
+        None => Box::new(std::iter::once(None)),
+        // 从第一个还没有输出的帧继续渲染
+        Some((start, end)) if options.resume_frames => {
+            let output = output.as_ref().unwrap();
+            let exists = |frame| Path::new(&frame_path(output, Some(frame))).exists();
+            match (start..=end).find(|&frame| !exists(frame)) {
+                Some(first) => Box::new((first..=end).map(Some)),
+                None => {
+                    if options.progress {
+                        eprintln!("All frames have been rendered.");
+                    }
+                    Box::new(std::iter::empty())
+                }
             }
-            content.push_str(&color.format_str(SAMPLES_PER_PIXEL as f64));
         }
-    }
-    println!("{}", content);
-    eprintln!("Done.");
-}
+        Some((start, end)) => Box::new((start..=end).map(Some)),
+    };
 
-// 光线的颜色计算
-fn ray_color(ray: &Ray, hittable: &dyn Hit, depth: u64) -> Color {
-    // 超过最大深度，直接变成黑色
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+    // 物体编号从 1 开始，0 表示没有编号
+    let world = match options.aov.contains(&Pass::Object) {
+        true => scene
+            .world
+            .into_iter()
+            .enumerate()
+            .map(|(i, object)| Box::new(Numbered::new(object, i + 1)) as Box<dyn Hit>)
+            .collect(),
+        false => scene.world,
+    };
+    let world = bvh::accelerate(world);
+
+    let (width, height) = (image.width, image.height());
+    let mut resumed = match resumed {
+        Some(film) if (film.width, film.height) != (width, height) => exit(&format!(
+            "the checkpoint is {}x{}, but the image is {width}x{height}",
+            film.width, film.height
+        )),
+        resumed => resumed,
+    };
 
-    // 射线命中物体
-    if let Some(record) = hittable.hit(ray, 0.001, f64::INFINITY) {
-        // 命中物体根据材料散射光线
-        return match record.material.scatter(ray, &record) {
-            Some((attenuation, scattered)) => {
-                attenuation * ray_color(&scattered, hittable, depth - 1)
+    // 恢复渲染时默认继续保存到同一个检查点文件
+    let checkpoint = options.checkpoint.clone().or(options.resume.clone());
+    let settings = Settings {
+        width,
+        height,
+        samples: image.samples,
+        max_depth: image.max_depth,
+        lights: match options.light_sampling {
+            true => scene.lights,
+            false => Lights::new(),
+        },
+        environment: match options.light_sampling {
+            true => scene.background.environment().cloned(),
+            false => None,
+        },
+        background: scene.background,
+        threads: options.threads,
+        progress: options.progress,
+        checkpoint: checkpoint.map(|path| Checkpoint {
+            path,
+            interval: options.checkpoint_interval,
+            samples: options.checkpoint_samples,
+        }),
+        adaptive: options.adaptive.map(|threshold| Adaptive {
+            min_samples: options.min_samples,
+            threshold,
+            display,
+        }),
+    };
+
+    for frame in frames {
+        let camera = match (&scene.animation, frame, range) {
+            (Some(animation), Some(frame), Some((start, end))) => {
+                if options.progress {
+                    // 用 u128 计算，帧的范围可以是整个 u64
+                    let (index, count) = (frame - start, end - start);
+                    eprintln!(
+                        "Frame {frame} ({} of {})",
+                        index as u128 + 1,
+                        count as u128 + 1
+                    );
+                }
+                let settings = animation.camera(&scene.camera, frame as f64);
+                settings.camera(image.ratio)
             }
-            None => Color::new(0.0, 0.0, 0.0),
+            _ => scene.camera.camera(image.ratio),
         };
-    }
+        // 降噪和辅助图像都需要和颜色一起累加的辅助信息
+        let needs_features = options.denoise || !options.aov.is_empty();
+        let mut film = match resumed.take() {
+            Some(film) if needs_features && film.features.is_none() => {
+                exit("the checkpoint has no AOVs, which --denoise and --aov need")
+            }
+            Some(film) => film,
+            None if needs_features => Film::new(width, height, seed).with_features(),
+            None => Film::new(width, height, seed),
+        };
+        let stats = render::render(&world, &camera, &settings, &mut film);
+        if options.progress {
+            eprintln!("{}", stats.summary());
+        }
+        if let Some(path) = &options.stats_json {
+            let path = frame_path(path, frame);
+            if let Err(error) = std::fs::write(&path, stats.json()) {
+                exit(&format!("failed to write statistics {path}: {error}"));
+            }
+        }
 
-    // 射线未命中，射线的单位向量
-    let unit = ray.direction().unit();
-    // 因为需要得到上下渐变的背景图，所以需要对 y 进行插值。
-    let t = 0.5 * (unit.y + 1.0);
-    // 线性插值，根据不同的光线得到在下面这个范围里的不同的颜色，并且是渐变色。
-    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
-}
+        let features = aov::Buffers::new(&film);
+        let denoised = match (&features, options.denoise) {
+            (Some(features), true) => Some(denoise::denoise(&film, features, settings.threads)),
+            _ => None,
+        };
+        let result = denoised.as_ref().unwrap_or(&film);
+        let output = output.as_ref().map(|path| frame_path(path, frame));
+        let result = match &output {
+            Some(path) => {
+                File::create(path).and_then(|file| write_film(result, &display, format, file))
+            }
+            None => write_film(result, &display, format, io::stdout().lock()),
+        };
+        if let Err(error) = result {
+            exit(&format!("failed to write image: {error}"));
+        }
 
-fn random_scene() -> World {
-    let mut rng = rand::thread_rng();
-    let mut world = World::new();
-
-    let ground = Rc::new(Lambert::new(Color::new(0.5, 0.5, 0.5)));
-    let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground);
-
-    world.push(Box::new(ground));
-
-    for a in -11..=11 {
-        for b in -11..=11 {
-            let choose_mat: f64 = rng.gen();
-            let center = Point3::new(
-                (a as f64) + rng.gen_range(0.0..0.9),
-                0.2,
-                (b as f64) + rng.gen_range(0.0..0.9),
-            );
-
-            if choose_mat < 0.8 {
-                let albedo = Color::random(0.0..1.0) * Color::random(0.0..1.0);
-                let sphere_mat = Rc::new(Lambert::new(albedo));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
-            } else if choose_mat < 0.95 {
-                let albedo = Color::random(0.4..1.0);
-                let fuzz = rng.gen_range(0.0..0.5);
-                let sphere_mat = Rc::new(Metal::new(albedo, fuzz));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
-            } else {
-                // Glass
-                let sphere_mat = Rc::new(Dielectric::new(1.5));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere));
+        if let Some(path) = &options.heatmap {
+            let path = frame_path(path, frame);
+            let format = Format::from_path(&path).unwrap_or(Format::Ppm);
+            // 高动态范围的格式直接保存采样数
+            let result = File::create(&path).and_then(|file| match format.is_hdr() {
+                true => write_hdr(&HdrImage::sample_counts(&film), format, file),
+                false => write(&Image::heatmap(&film, settings.samples), format, file),
+            });
+            if let Err(error) = result {
+                exit(&format!("failed to write heatmap: {error}"));
+            }
+        }
+        if let (Some(output), Some(features)) = (&output, &features) {
+            let output = Path::new(output);
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            for &pass in &options.aov {
+                let name = format!("{stem}-{}.{}", pass.name(), format.extension());
+                let path = output.with_file_name(name);
+                let result = File::create(&path).and_then(|file| match format.is_hdr() {
+                    true => write_hdr(&features.hdr(pass), format, file),
+                    false => write(&features.image(pass), format, file),
+                });
+                if let Err(error) = result {
+                    exit(&format!(
+                        "{}: failed to write image: {error}",
+                        path.display()
+                    ));
+                }
             }
         }
     }
+    if options.progress {
+        eprintln!("Done.");
+    }
+}
+
+// 动画的每一帧在文件名后面加上帧号，比如 frame.png 的第 1 帧是 frame_0001.png
+fn frame_path(path: &str, frame: Option<u64>) -> String {
+    let Some(frame) = frame else {
+        return path.to_string();
+    };
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}_{frame:04}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{frame:04}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
 
-    let mat1 = Rc::new(Dielectric::new(1.5));
-    let mat2 = Rc::new(Lambert::new(Color::new(0.4, 0.2, 0.1)));
-    let mat3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+// 高动态范围的格式直接保存线性的颜色，其他的格式经过曝光和色调映射
+fn write_film(film: &Film, display: &Display, format: Format, out: impl Write) -> io::Result<()> {
+    let mut out = BufWriter::new(out);
+    match format.is_hdr() {
+        true => HdrImage::new(film).write(format, &mut out)?,
+        false => Image::new(film, display).write(format, &mut out)?,
+    }
+    out.flush()
+}
 
-    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
-    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
-    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
+fn write(image: &Image, format: Format, out: impl Write) -> io::Result<()> {
+    let mut out = BufWriter::new(out);
+    image.write(format, &mut out)?;
+    out.flush()
+}
 
-    world.push(Box::new(sphere1));
-    world.push(Box::new(sphere2));
-    world.push(Box::new(sphere3));
+fn write_hdr(image: &HdrImage, format: Format, out: impl Write) -> io::Result<()> {
+    let mut out = BufWriter::new(out);
+    image.write(format, &mut out)?;
+    out.flush()
+}
 
-    world
+fn exit(message: &str) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1);
 }
@@ -1,63 +1,164 @@
-use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
-use crate::vector3::{Color, Vector3};
+use crate::sampler::Sampler;
+use crate::texture::{SolidColor, Texture};
+use crate::vector3::{Color, Point3, Vector3};
+
+// 材质的种类，用来输出材质的辅助图像
+#[derive(Clone, Copy)]
+pub enum Kind {
+    Lambert,
+    Metal,
+    Dielectric,
+    DiffuseLight,
+    Isotropic,
+}
+
+impl Kind {
+    // 按照定义的顺序，检查点里保存的是在这里的位置
+    pub const ALL: [Kind; 5] = [
+        Kind::Lambert,
+        Kind::Metal,
+        Kind::Dielectric,
+        Kind::DiffuseLight,
+        Kind::Isotropic,
+    ];
+}
 
 // 材质
-pub trait Material {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Color, Ray)>;
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray: &Ray, record: &HitRecord, sampler: &mut Sampler)
+        -> Option<(Color, Ray)>;
+
+    fn kind(&self) -> Kind;
+
+    /// 表面本身的颜色，和光照无关，输出给合成和降噪使用
+    fn albedo(&self, record: &HitRecord) -> Color;
+
+    /// 材质本身发出的光，默认不发光
+    fn emitted(&self, _u: f64, _v: f64, _point: Point3) -> Color {
+        Color::default()
+    }
+
+    /// 发光的材质，用在球和四边形上的时候可以作为光源采样
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// scatter 采样到 direction 方向的概率密度（立体角）。
+    /// 镜面反射和折射这样只能由 scatter 自己采样的材质返回 None，不对光源采样。
+    fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord, _direction: Vector3) -> Option<f64> {
+        None
+    }
+
+    /// 散射到 direction 方向的颜色，也就是 BSDF 乘以余弦，
+    /// 和 scatter 的衰减满足 衰减 = evaluate / scattering_pdf
+    fn evaluate(&self, _ray: &Ray, _record: &HitRecord, _direction: Vector3) -> Color {
+        Color::default()
+    }
 }
 
 pub struct Lambert {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambert {
     pub fn new(albedo: Color) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
         Lambert { albedo }
     }
 }
 
 impl Material for Lambert {
-    fn scatter(&self, _: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
-        let mut direction = record.normal + Vector3::random_unit();
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<(Color, Ray)> {
+        let mut direction = record.normal + Vector3::random_unit(sampler);
 
         if direction.near_zero() {
             direction = record.normal;
         }
-        let scattered = Ray::new(record.point, direction);
+        let scattered = Ray::new(record.point, direction, ray.time);
 
-        Some((self.albedo, scattered))
+        let albedo = self.albedo.value(record.u, record.v, record.point);
+        Some((albedo, scattered))
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Lambert
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Color {
+        self.albedo.value(record.u, record.v, record.point)
+    }
+
+    // 按余弦分布采样
+    fn scattering_pdf(&self, _: &Ray, record: &HitRecord, direction: Vector3) -> Option<f64> {
+        let cos = record.normal.dot(direction.unit());
+        Some(cos.max(0.0) / PI)
+    }
+
+    fn evaluate(&self, _: &Ray, record: &HitRecord, direction: Vector3) -> Color {
+        let cos = record.normal.dot(direction.unit());
+        cos.max(0.0) / PI * self.albedo.value(record.u, record.v, record.point)
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     // 模糊属性
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Metal { albedo, fuzz }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<(Color, Ray)> {
         let direction = ray.direction.unit();
 
         let normal = record.normal;
         let reflected = direction - 2.0 * direction.dot(normal) * normal;
-        let ray = reflected + self.fuzz * Vector3::random_unit();
-        let scattered = Ray::new(record.point, ray);
+        let reflected = reflected + self.fuzz * Vector3::random_unit(sampler);
+        let scattered = Ray::new(record.point, reflected, ray.time);
 
         match scattered.direction.dot(normal) > 0.0 {
-            true => Some((self.albedo, scattered)),
+            true => Some((
+                self.albedo.value(record.u, record.v, record.point),
+                scattered,
+            )),
             false => None,
         }
     }
+
+    fn kind(&self) -> Kind {
+        Kind::Metal
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Color {
+        self.albedo.value(record.u, record.v, record.point)
+    }
 }
 
 pub struct Dielectric {
@@ -84,7 +185,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<(Color, Ray)> {
         let ratio = match record.face {
             true => 1.0 / self.refraction,
             false => self.refraction,
@@ -95,16 +201,153 @@ impl Material for Dielectric {
         let cos = (-1.0 * direction).dot(normal).min(1.0);
         let sin = (1.0 - cos.powi(2)).sqrt();
 
-        let mut rand = rand::thread_rng();
         let cannot_refract = ratio * sin > 1.0;
-        let will_reflect = Self::reflectance(cos, ratio) > rand.gen();
+        let will_reflect = Self::reflectance(cos, ratio) > sampler.gen();
 
         let direction = match cannot_refract || will_reflect {
             true => direction - 2.0 * direction.dot(normal) * normal,
             false => Self::refract(direction, normal, ratio),
         };
 
-        let scattered = Ray::new(record.point, direction);
+        let scattered = Ray::new(record.point, direction, ray.time);
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
+
+    fn kind(&self) -> Kind {
+        Kind::Dielectric
+    }
+
+    // 玻璃不吸收光
+    fn albedo(&self, _: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+// 发光的材质，不反射光线
+pub struct DiffuseLight {
+    color: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(color: Color) -> DiffuseLight {
+        Self::with_texture(Arc::new(SolidColor::new(color)))
+    }
+
+    pub fn with_texture(color: Arc<dyn Texture>) -> DiffuseLight {
+        DiffuseLight { color }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut Sampler) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::DiffuseLight
+    }
+
+    // 发出的光通常大于 1，限制在 [0, 1] 之间
+    fn albedo(&self, record: &HitRecord) -> Color {
+        let color = self.color.value(record.u, record.v, record.point);
+        Color::new(color.x.min(1.0), color.y.min(1.0), color.z.min(1.0))
+    }
+
+    fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
+        self.color.value(u, v, point)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+// 参与介质的相函数，各个方向散射的概率相同。
+// 设置了各向异性参数 g 之后使用 Henyey-Greenstein 相函数，
+// g > 0 的时候偏向前方散射，g < 0 的时候偏向后方散射。
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+    anisotropy: f64,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic {
+            albedo,
+            anisotropy: 0.0,
+        }
+    }
+
+    /// g 在 (-1, 1) 之间
+    pub fn with_anisotropy(mut self, g: f64) -> Isotropic {
+        self.anisotropy = g.clamp(-0.999, 0.999);
+        self
+    }
+
+    // 散射方向和原来方向的夹角余弦是 cos 的概率密度
+    fn phase(&self, cos: f64) -> f64 {
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    // 按照 Henyey-Greenstein 相函数采样散射方向和原来方向夹角的余弦
+    fn sample_cos(&self, sampler: &mut Sampler) -> f64 {
+        let g = self.anisotropy;
+        let xi = sampler.gen();
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<(Color, Ray)> {
+        let albedo = self.albedo.value(record.u, record.v, record.point);
+        if self.anisotropy == 0.0 {
+            let scattered = Ray::new(record.point, Vector3::random_unit(sampler), ray.time);
+            return Some((albedo, scattered));
+        }
+
+        // 以原来的方向为轴，建立一组正交基
+        let w = ray.direction.unit();
+        let (u, v) = w.basis();
+
+        let cos = self.sample_cos(sampler);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.gen();
+        let direction = sin * phi.cos() * u + sin * phi.sin() * v + cos * w;
+
+        // 按照相函数本身采样，权重就是反照率
+        Some((albedo, Ray::new(record.point, direction, ray.time)))
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Isotropic
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Color {
+        self.albedo.value(record.u, record.v, record.point)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, _: &HitRecord, direction: Vector3) -> Option<f64> {
+        let cos = ray.direction.unit().dot(direction.unit());
+        Some(self.phase(cos))
+    }
+
+    fn evaluate(&self, ray: &Ray, record: &HitRecord, direction: Vector3) -> Color {
+        let cos = ray.direction.unit().dot(direction.unit());
+        self.phase(cos) * self.albedo.value(record.u, record.v, record.point)
+    }
 }
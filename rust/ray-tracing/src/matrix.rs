@@ -0,0 +1,141 @@
+use std::ops::Mul;
+
+use crate::vector3::{Point3, Vector3};
+
+// 4x4 的仿射变换矩阵，按行存储，点和向量都是列向量
+#[derive(Clone, Copy)]
+pub struct Matrix4 {
+    rows: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix4 { rows }
+    }
+
+    pub fn translation(offset: Vector3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.rows[0][3] = offset.x;
+        matrix.rows[1][3] = offset.y;
+        matrix.rows[2][3] = offset.z;
+        matrix
+    }
+
+    /// 每个轴的缩放比例可以不同
+    pub fn scaling(factor: Vector3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.rows[0][0] = factor.x;
+        matrix.rows[1][1] = factor.y;
+        matrix.rows[2][2] = factor.z;
+        matrix
+    }
+
+    /// 绕着穿过原点的任意轴旋转，角度的单位是度，从轴的正方向看过去是逆时针
+    pub fn rotation(axis: Vector3, degrees: f64) -> Matrix4 {
+        let Vector3 { x, y, z } = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+
+        // Rodrigues 旋转公式
+        let mut matrix = Matrix4::identity();
+        matrix.rows[0][..3].copy_from_slice(&[
+            cos + x * x * k,
+            x * y * k - z * sin,
+            x * z * k + y * sin,
+        ]);
+        matrix.rows[1][..3].copy_from_slice(&[
+            y * x * k + z * sin,
+            cos + y * y * k,
+            y * z * k - x * sin,
+        ]);
+        matrix.rows[2][..3].copy_from_slice(&[
+            z * x * k - y * sin,
+            z * y * k + x * sin,
+            cos + z * z * k,
+        ]);
+        matrix
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Matrix4 { rows }
+    }
+
+    /// 逆矩阵，矩阵不可逆（比如某个轴缩放成 0）的时候返回 None
+    pub fn inverse(&self) -> Option<Matrix4> {
+        // 高斯-约当消元，每一列选绝对值最大的主元
+        let mut a = self.rows;
+        let mut inverse = Matrix4::identity().rows;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for i in 0..4 {
+                let factor = a[i][column];
+                if i == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[i][j] -= factor * a[column][j];
+                    inverse[i][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4 { rows: inverse })
+    }
+
+    /// 变换一个点，包括平移
+    pub fn point(&self, point: Point3) -> Point3 {
+        let r = &self.rows;
+        Point3::new(
+            r[0][0] * point.x + r[0][1] * point.y + r[0][2] * point.z + r[0][3],
+            r[1][0] * point.x + r[1][1] * point.y + r[1][2] * point.z + r[1][3],
+            r[2][0] * point.x + r[2][1] * point.y + r[2][2] * point.z + r[2][3],
+        )
+    }
+
+    /// 变换一个方向，不受平移的影响
+    pub fn vector(&self, vector: Vector3) -> Vector3 {
+        let r = &self.rows;
+        Vector3::new(
+            r[0][0] * vector.x + r[0][1] * vector.y + r[0][2] * vector.z,
+            r[1][0] * vector.x + r[1][1] * vector.y + r[1][2] * vector.z,
+            r[2][0] * vector.x + r[2][1] * vector.y + r[2][2] * vector.z,
+        )
+    }
+}
+
+/// a * b 表示先做 b 变换，再做 a 变换
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+        Matrix4 { rows }
+    }
+}
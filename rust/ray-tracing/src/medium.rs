@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vector3::Vector3;
+
+// 密度均匀的参与介质（雾、烟），边界可以是任意凸的物体。
+// 射线在介质里走过的距离服从指数分布，在散射点由相函数材质决定新的方向。
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hit>,
+    // 密度的负倒数，用来把均匀分布的随机数转换成自由程
+    negative_inverse_density: f64,
+    phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// 密度必须是正的有限值，否则自由程是 NaN 或者负数
+    pub fn new(boundary: Arc<dyn Hit>, density: f64, phase: Arc<dyn Material>) -> ConstantMedium {
+        debug_assert!(density.is_finite() && density > 0.0, "介质的密度必须是正数");
+        ConstantMedium {
+            boundary,
+            negative_inverse_density: -1.0 / density,
+            phase,
+        }
+    }
+}
+
+// 射线上的种子来自每次采样的采样器，再和进入边界的位置混合，
+// 同一条射线穿过几个介质的时候每个介质的随机数是独立的
+fn free_flight_random(ray: &Ray, entry: f64) -> f64 {
+    let hash = sampler::mix(ray.seed ^ sampler::mix(entry.to_bits()));
+    // (0, 1] 之间，取对数的时候不会得到无穷大
+    ((hash >> 11) + 1) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+impl Hit for ConstantMedium {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        // 在整条直线上找进入和离开边界的位置，射线的起点在介质里面的时候，
+        // 进入的位置在起点的后面，只从 min 开始找的话会把离开的位置当成进入的位置
+        let entry = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self.boundary.hit(ray, entry.t + 1e-4, f64::INFINITY)?;
+
+        let start = entry.t.max(min);
+        let end = exit.t.min(max);
+        if start >= end {
+            return None;
+        }
+
+        let length = ray.direction.length();
+        let inside = (end - start) * length;
+        let distance = self.negative_inverse_density * free_flight_random(ray, entry.t).ln();
+        if distance > inside {
+            return None;
+        }
+
+        let t = start + distance / length;
+        Some(HitRecord {
+            point: ray.at(t),
+            // 介质里的散射点没有表面，法线和朝向都没有意义
+            normal: Vector3::new(1.0, 0.0, 0.0),
+            t,
+            u: 0.0,
+            v: 0.0,
+            material: Arc::clone(&self.phase),
+            face: true,
+            object: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use crate::vector3::{Color, Point3};
+
+    #[test]
+    fn free_flight_follows_sampler() {
+        let white = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let boundary = Arc::new(Sphere::new(Point3::default(), 1.0, white.clone()));
+        let medium = ConstantMedium::new(boundary, 0.5, white);
+
+        // 同一条射线换一个种子得到不同的散射点，穿过的比例是 exp(-密度 * 距离)
+        let mut sampler = Sampler::new(5);
+        let count = 20_000;
+        let mut passed = 0;
+        let mut distances = Vec::new();
+        for _ in 0..count {
+            let origin = Point3::new(0.0, 0.0, -5.0);
+            let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 2.0), 0.0).with_seed(&mut sampler);
+            match medium.hit(&ray, 0.001, f64::INFINITY) {
+                Some(record) => distances.push(record.t),
+                None => passed += 1,
+            }
+        }
+        let expected = (-0.5f64 * 2.0).exp();
+        let fraction = passed as f64 / count as f64;
+        assert!(
+            (fraction - expected).abs() < 0.01,
+            "{fraction} vs {expected}"
+        );
+        distances.sort_by(f64::total_cmp);
+        distances.dedup();
+        assert!(distances.len() > count / 2);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::{Hit, HitRecord, World};
+use crate::material::{Lambert, Material};
+use crate::ray::Ray;
+use crate::triangle::Triangle;
+use crate::vector3::{Color, Point3, Vector3};
+
+// 三角形网格，内部使用 BVH 加速求交
+pub struct Mesh {
+    bvh: BvhNode,
+}
+
+// 加载网格时对顶点的变换：先缩放再平移
+pub struct Placement {
+    pub position: Vector3,
+    pub scale: f64,
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Result<Mesh, String> {
+        if triangles.is_empty() {
+            return Err(String::from("mesh has no triangles"));
+        }
+
+        let world: World = triangles
+            .into_iter()
+            .map(|triangle| Box::new(triangle) as Box<dyn Hit>)
+            .collect();
+        Ok(Mesh {
+            bvh: BvhNode::new(world),
+        })
+    }
+
+    /// 加载 Wavefront OBJ 文件，`.mtl` 里的漫反射颜色（Kd）转换成 Lambert 材质，
+    /// 没有指定材质的面使用 material
+    pub fn load(
+        path: &Path,
+        material: Arc<dyn Material>,
+        placement: &Placement,
+    ) -> Result<Mesh, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let error =
+            |line: usize, message: &str| format!("{}: line {line}: {message}", path.display());
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+        let mut current = Arc::clone(&material);
+        let mut triangles = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.split('#').next().unwrap();
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                Some("v") => {
+                    let vertex =
+                        vector3(&mut words).ok_or_else(|| error(number, "invalid vertex"))?;
+                    positions.push(placement.scale * vertex + placement.position);
+                }
+                Some("vn") => {
+                    let normal =
+                        vector3(&mut words).ok_or_else(|| error(number, "invalid normal"))?;
+                    normals.push(normal.unit());
+                }
+                Some("vt") => {
+                    let mut uv = words.map(str::parse::<f64>);
+                    match (uv.next(), uv.next()) {
+                        (Some(Ok(u)), Some(Ok(v))) => uvs.push((u, v)),
+                        // 只有 u 的时候 v 默认是 0
+                        (Some(Ok(u)), None) => uvs.push((u, 0.0)),
+                        _ => return Err(error(number, "invalid texture coordinate")),
+                    }
+                }
+                Some("f") => {
+                    let mut vertices = Vec::new();
+                    for word in words {
+                        let vertex = face_vertex(word, positions.len(), uvs.len(), normals.len())
+                            .ok_or_else(|| {
+                            error(number, &format!("invalid face vertex `{word}`"))
+                        })?;
+                        vertices.push(vertex);
+                    }
+                    if vertices.len() < 3 {
+                        return Err(error(number, "a face needs at least 3 vertices"));
+                    }
+
+                    // 多边形按照扇形分成三角形
+                    for i in 1..vertices.len() - 1 {
+                        let corners = [vertices[0], vertices[i], vertices[i + 1]];
+                        let points = corners.map(|(position, _, _)| positions[position]);
+                        let mut triangle = Triangle::new(points, Arc::clone(&current));
+                        if let [(_, Some(t0), _), (_, Some(t1), _), (_, Some(t2), _)] = corners {
+                            triangle = triangle.with_uvs([uvs[t0], uvs[t1], uvs[t2]]);
+                        }
+                        if let [(_, _, Some(n0)), (_, _, Some(n1)), (_, _, Some(n2))] = corners {
+                            triangle =
+                                triangle.with_normals([normals[n0], normals[n1], normals[n2]]);
+                        }
+                        triangles.push(triangle);
+                    }
+                }
+                Some("mtllib") => {
+                    let name = line.trim_start()["mtllib".len()..].trim();
+                    let library = path.with_file_name(name);
+                    materials.extend(load_materials(&library)?);
+                }
+                Some("usemtl") => {
+                    let name = line.trim_start()["usemtl".len()..].trim();
+                    current = match materials.get(name) {
+                        Some(material) => Arc::clone(material),
+                        None => return Err(error(number, &format!("unknown material `{name}`"))),
+                    };
+                }
+                // 分组、平滑组等暂时用不到
+                _ => {}
+            }
+        }
+
+        Mesh::new(triangles).map_err(|message| format!("{}: {message}", path.display()))
+    }
+}
+
+impl Hit for Mesh {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        self.bvh.hit(ray, min, max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+fn vector3<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<Vector3> {
+    let x = words.next()?.parse().ok()?;
+    let y = words.next()?.parse().ok()?;
+    let z = words.next()?.parse().ok()?;
+    Some(Point3::new(x, y, z))
+}
+
+// 面的顶点格式是 `v`、`v/vt`、`v//vn` 或者 `v/vt/vn`，序号从 1 开始，负数表示倒数
+fn face_vertex(
+    word: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> Option<(usize, Option<usize>, Option<usize>)> {
+    let mut parts = word.split('/');
+    let position = obj_index(parts.next()?, positions)?;
+    let mut optional = |count| match parts.next() {
+        Some(index) if !index.is_empty() => obj_index(index, count).map(Some),
+        _ => Some(None),
+    };
+    let uv = optional(uvs)?;
+    let normal = optional(normals)?;
+    Some((position, uv, normal))
+}
+
+fn obj_index(word: &str, count: usize) -> Option<usize> {
+    let index: i64 = word.parse().ok()?;
+    let index = match index {
+        0 => return None,
+        i if i > 0 => i - 1,
+        i => count as i64 + i,
+    };
+    (0..count as i64).contains(&index).then_some(index as usize)
+}
+
+// 只读取材质的漫反射颜色
+fn load_materials(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    let mut materials = HashMap::new();
+    let mut name: Option<String> = None;
+    let mut color = Color::new(0.8, 0.8, 0.8);
+    let mut finish = |name: Option<String>, color: Color| {
+        if let Some(name) = name {
+            let material: Arc<dyn Material> = Arc::new(Lambert::new(color));
+            materials.insert(name, material);
+        }
+    };
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("newmtl") => {
+                finish(name.take(), color);
+                name = Some(line.trim_start()["newmtl".len()..].trim().to_string());
+                color = Color::new(0.8, 0.8, 0.8);
+            }
+            Some("Kd") => {
+                color = vector3(&mut words)
+                    .ok_or_else(|| format!("{}: line {}: invalid Kd", path.display(), index + 1))?;
+            }
+            _ => {}
+        }
+    }
+    finish(name, color);
+
+    Ok(materials)
+}
@@ -0,0 +1,213 @@
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::vector3::{Color, Point3, Vector3};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin 梯度噪声，整数格点上放随机的单位梯度向量，格子里面用 Hermite 曲线平滑插值
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut sampler = Sampler::new(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vector3::random_unit(&mut sampler))
+            .collect();
+        let permutations = [(); 3].map(|_| Self::permutation(&mut sampler));
+        Perlin {
+            gradients,
+            permutations,
+        }
+    }
+
+    // Fisher-Yates 洗牌
+    fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+        let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let j = ((sampler.gen() * (i + 1) as f64) as usize).min(i);
+            permutation.swap(i, j);
+        }
+        permutation
+    }
+
+    /// 在 [-1, 1] 之间，整数格点上的值是 0
+    pub fn noise(&self, point: Point3) -> f64 {
+        let floor = [point.x.floor(), point.y.floor(), point.z.floor()];
+        let fraction = [point.x - floor[0], point.y - floor[1], point.z - floor[2]];
+        let smooth = fraction.map(|t| t * t * (3.0 - 2.0 * t));
+
+        let [px, py, pz] = &self.permutations;
+        let mut accumulated = 0.0;
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    // 负数的格点也需要落在 [0, 256) 里，按位与正好是取模
+                    let index = |axis: usize, offset: usize| {
+                        (floor[axis] as i64 + offset as i64) as usize & (POINT_COUNT - 1)
+                    };
+                    let gradient =
+                        self.gradients[px[index(0, i)] ^ py[index(1, j)] ^ pz[index(2, k)]];
+                    let weight = Vector3::new(
+                        fraction[0] - i as f64,
+                        fraction[1] - j as f64,
+                        fraction[2] - k as f64,
+                    );
+
+                    let blend = |t: f64, corner: usize| match corner {
+                        0 => 1.0 - t,
+                        _ => t,
+                    };
+                    accumulated += blend(smooth[0], i)
+                        * blend(smooth[1], j)
+                        * blend(smooth[2], k)
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accumulated
+    }
+
+    /// 多个频率的噪声叠加，频率每次翻倍，权重每次减半，结果在 [0, 2) 之间
+    pub fn turbulence(&self, point: Point3, depth: u32) -> f64 {
+        let mut accumulated = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accumulated += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+        accumulated
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// 大理石，沿着 z 方向的正弦条纹被湍流扰动
+pub struct Marble {
+    noise: Perlin,
+    // 条纹的频率
+    scale: f64,
+    base: Color,
+    vein: Color,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f64, base: Color, vein: Color) -> Marble {
+        Marble {
+            noise: Perlin::new(seed),
+            scale,
+            base,
+            vein,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let phase = self.scale * point.z + 10.0 * self.noise.turbulence(point, 7);
+        lerp(self.vein, self.base, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// 木纹，围绕 y 轴的同心年轮，年轮的半径被噪声扰动
+pub struct Wood {
+    noise: Perlin,
+    // 每个单位长度里年轮的数量
+    scale: f64,
+    light: Color,
+    dark: Color,
+}
+
+impl Wood {
+    pub fn new(seed: u64, scale: f64, light: Color, dark: Color) -> Wood {
+        Wood {
+            noise: Perlin::new(seed),
+            scale,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let radius = (point.x * point.x + point.z * point.z).sqrt();
+        let rings = self.scale * radius + 2.0 * self.noise.noise(4.0 * point);
+        let t = rings - rings.floor();
+        // 年轮的边缘颜色比较深
+        lerp(self.light, self.dark, t * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_deterministic() {
+        let (a, b, c) = (Perlin::new(7), Perlin::new(7), Perlin::new(8));
+        let mut sampler = Sampler::new(1);
+        let mut different = false;
+        for _ in 0..1000 {
+            let point = Point3::random(-50.0..50.0, &mut sampler);
+            assert_eq!(a.noise(point), b.noise(point));
+            assert_eq!(a.turbulence(point, 7), b.turbulence(point, 7));
+            different |= a.noise(point) != c.noise(point);
+        }
+        assert!(different);
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let perlin = Perlin::new(3);
+        let mut sampler = Sampler::new(2);
+        for _ in 0..100_000 {
+            let point = Point3::random(-100.0..100.0, &mut sampler);
+            let noise = perlin.noise(point);
+            assert!((-1.0..=1.0).contains(&noise), "noise {noise} at {point:?}");
+            let turbulence = perlin.turbulence(point, 7);
+            assert!((0.0..2.0).contains(&turbulence), "turbulence {turbulence}");
+        }
+    }
+
+    #[test]
+    fn noise_vanishes_on_lattice_points() {
+        let perlin = Perlin::new(4);
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    let point = Point3::new(x as f64, y as f64, z as f64);
+                    assert!(perlin.noise(point).abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let perlin = Perlin::new(5);
+        let mut sampler = Sampler::new(6);
+        for _ in 0..10_000 {
+            let point = Point3::random(-20.0..20.0, &mut sampler);
+            let step = 1e-6 * Vector3::random_unit(&mut sampler);
+            let difference = (perlin.noise(point + step) - perlin.noise(point)).abs();
+            assert!(difference < 1e-4, "jump of {difference} at {point:?}");
+        }
+
+        // 跨过格子边界的时候也不能跳变
+        for boundary in -5..5 {
+            let boundary = boundary as f64;
+            for offset in [0.25, 0.5, 0.75] {
+                let below = Point3::new(boundary - 1e-9, offset, offset);
+                let above = Point3::new(boundary + 1e-9, offset, offset);
+                assert!((perlin.noise(below) - perlin.noise(above)).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord, World};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector3::{Point3, Vector3};
+
+// 平行四边形，由一个角和两条边决定
+pub struct Quad {
+    corner: Point3,
+    u: Vector3,
+    v: Vector3,
+    normal: Vector3,
+    // 平面方程 normal·p = d
+    d: f64,
+    area: f64,
+    // 用来计算交点在两条边上的坐标
+    w: Vector3,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vector3, v: Vector3, material: Arc<dyn Material>) -> Quad {
+        let n = u.cross(v);
+        let normal = n.unit();
+        Quad {
+            corner,
+            u,
+            v,
+            normal,
+            d: normal.dot(corner),
+            area: n.length(),
+            w: n / n.dot(n),
+            material,
+        }
+    }
+}
+
+impl Hit for Quad {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        // 射线和平面平行
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin)) / denominator;
+        if t < min || max < t {
+            return None;
+        }
+
+        // 交点在两条边上的坐标都在 [0, 1] 之间才在四边形内
+        let point = ray.at(t);
+        let planar = point - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let face = denominator < 0.0;
+        let normal = match face {
+            true => self.normal,
+            false => -1.0 * self.normal,
+        };
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u: alpha,
+            v: beta,
+            face,
+            object: 0,
+            material: Arc::clone(&self.material),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let diagonal1 = Aabb::from_points(self.corner, self.corner + self.u + self.v);
+        let diagonal2 = Aabb::from_points(self.corner + self.u, self.corner + self.v);
+        let bbox = diagonal1.union(diagonal2);
+        // 和坐标轴平行的四边形包围盒厚度为 0，稍微扩大一点
+        let padding = Vector3::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(bbox.min - padding, bbox.max + padding))
+    }
+
+    // 在四边形上按面积均匀采样一个点
+    fn random_direction(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let point = self.corner + sampler.gen() * self.u + sampler.gen() * self.v;
+        point - origin
+    }
+
+    // 面积上的概率密度 1 / area 转换成立体角上的概率密度
+    fn pdf(&self, origin: Point3, direction: Vector3) -> f64 {
+        let Some(record) = self.hit(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) else {
+            return 0.0;
+        };
+
+        let length_squared = direction.dot(direction);
+        let distance_squared = record.t * record.t * length_squared;
+        let cos = direction.dot(self.normal).abs() / length_squared.sqrt();
+        distance_squared / (cos * self.area)
+    }
+}
+
+// 无限大的平面，没有包围盒
+pub struct Plane {
+    point: Point3,
+    normal: Vector3,
+    // 平面上两个互相垂直的方向，纹理坐标沿着这两个方向每个单位重复一次
+    tangent: Vector3,
+    bitangent: Vector3,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vector3, material: Arc<dyn Material>) -> Plane {
+        let normal = normal.unit();
+        let axis = match normal.x.abs() > 0.9 {
+            true => Vector3::new(0.0, 1.0, 0.0),
+            false => Vector3::new(1.0, 0.0, 0.0),
+        };
+        let tangent = normal.cross(axis).unit();
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent: normal.cross(tangent),
+            material,
+        }
+    }
+}
+
+impl Hit for Plane {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.point - ray.origin).dot(self.normal) / denominator;
+        if t < min || max < t {
+            return None;
+        }
+
+        let face = denominator < 0.0;
+        let normal = match face {
+            true => self.normal,
+            false => -1.0 * self.normal,
+        };
+
+        let point = ray.at(t);
+        let planar = point - self.point;
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u: planar.dot(self.tangent).rem_euclid(1.0),
+            v: planar.dot(self.bitangent).rem_euclid(1.0),
+            face,
+            object: 0,
+            material: Arc::clone(&self.material),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// 轴对齐的长方体，由六个四边形组成，法线都朝外
+pub struct Cuboid {
+    sides: World,
+    bbox: Aabb,
+}
+
+impl Cuboid {
+    /// a 和 b 是长方体相对的两个角
+    pub fn new(a: Point3, b: Point3, material: Arc<dyn Material>) -> Cuboid {
+        let bbox = Aabb::from_points(a, b);
+        let (min, max) = (bbox.min, bbox.max);
+
+        let dx = Vector3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vector3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vector3::new(0.0, 0.0, max.z - min.z);
+
+        let mut sides = World::new();
+        let mut side =
+            |corner, u, v| sides.push(Box::new(Quad::new(corner, u, v, Arc::clone(&material))));
+        // 前、右、后、左、上、下，两条边的叉乘方向朝外
+        side(Point3::new(min.x, min.y, max.z), dx, dy);
+        side(Point3::new(max.x, min.y, max.z), -1.0 * dz, dy);
+        side(Point3::new(max.x, min.y, min.z), -1.0 * dx, dy);
+        side(Point3::new(min.x, min.y, min.z), dz, dy);
+        side(Point3::new(min.x, max.y, max.z), dx, -1.0 * dz);
+        side(Point3::new(min.x, min.y, min.z), dx, dz);
+
+        Cuboid { sides, bbox }
+    }
+}
+
+impl Hit for Cuboid {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        self.sides.hit(ray, min, max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
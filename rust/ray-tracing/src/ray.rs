@@ -1,13 +1,30 @@
+use crate::sampler::Sampler;
 use crate::vector3::{Point3, Vector3};
 
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vector3,
+    // 射线发出的时刻，在相机的快门时间内，用来实现运动模糊
+    pub time: f64,
+    // 采样器生成的随机数种子，参与介质用它决定自由程。Hit::hit 没有采样器，
+    // 追踪每条射线之前由渲染循环设置
+    pub seed: u64,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vector3) -> Ray {
-        Self { origin, direction }
+    pub fn new(origin: Point3, direction: Vector3, time: f64) -> Ray {
+        Self {
+            origin,
+            direction,
+            time,
+            seed: 0,
+        }
+    }
+
+    /// 用采样器的下一个随机数作为种子
+    pub fn with_seed(mut self, sampler: &mut Sampler) -> Ray {
+        self.seed = sampler.next_u64();
+        self
     }
 
     pub fn origin(&self) -> Point3 {
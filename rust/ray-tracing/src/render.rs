@@ -0,0 +1,727 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::aov;
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::color::Display;
+use crate::environment::Environment;
+use crate::film::Film;
+use crate::hittable::{Hit, HitRecord, Lights};
+use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
+use crate::stats::{self, Counters, Progress, Stats};
+use crate::vector3::{Color, Point3, Vector3};
+
+// 分块的边长，按像素计算
+const TILE_SIZE: u64 = 32;
+
+// 至少反射这么多次之后才开始随机结束路径
+const ROULETTE_DEPTH: u64 = 3;
+
+pub struct Settings {
+    pub width: u64,
+    pub height: u64,
+    // 每个像素的目标采样数
+    pub samples: u64,
+    pub max_depth: u64,
+    pub background: Background,
+    // 对光源直接采样，没有光源的时候只按照材质采样
+    pub lights: Lights,
+    // 作为光源采样的环境贴图，和 lights 一起随机选择
+    pub environment: Option<Arc<Environment>>,
+    pub threads: usize,
+    // 是否输出进度
+    pub progress: bool,
+    pub checkpoint: Option<Checkpoint>,
+    // 自适应采样，samples 是每个像素的最大采样数
+    pub adaptive: Option<Adaptive>,
+}
+
+// 每个像素至少采样 min_samples 次，之后每一轮开始前检查像素是否已经收敛，
+// 收敛的像素不再采样。收敛的标准是平均亮度的标准误差经过 display 的曝光、
+// 色调映射和 sRGB 编码之后小于 threshold（显示的值在 [0, 1] 之间）。
+pub struct Adaptive {
+    pub min_samples: u64,
+    pub threshold: f64,
+    pub display: Display,
+}
+
+impl Adaptive {
+    fn converged(&self, pixel: &Pixel) -> bool {
+        if pixel.count < self.min_samples.max(2) {
+            return false;
+        }
+        let n = pixel.count as f64;
+        let mean = pixel.color.luminance() / n;
+        let variance = ((pixel.square / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        // 平均值加减一个标准误差，显示出来的差别的一半。用差分而不是导数，
+        // 暗部 sRGB 的斜率很大，被截断成白色的像素误差是 0
+        let sigma = (variance / n).sqrt();
+        let error = (self.display.gray(mean + sigma) - self.display.gray(mean - sigma)) / 2.0;
+        error < self.threshold
+    }
+}
+
+// 定期把累加缓冲区保存到检查点文件
+pub struct Checkpoint {
+    pub path: String,
+    // 距离上次保存超过这个时间就保存
+    pub interval: Duration,
+    // 每个像素每完成这么多次采样保存一次
+    pub samples: Option<u64>,
+}
+
+// 一个像素的颜色的和、亮度的平方和以及采样数，
+// Film 记录辅助信息的时候 features 是 Some，和颜色一起累加
+#[derive(Clone, Copy, Default)]
+struct Pixel {
+    color: Color,
+    square: f64,
+    count: u64,
+    features: Option<aov::Sum>,
+}
+
+// 图片上的一块矩形区域，y 从图片的最上面一行开始
+struct Tile {
+    x: u64,
+    y: u64,
+    width: u64,
+    height: u64,
+}
+
+/// 分块渲染，把采样累加到 film 里，直到每个像素都达到目标采样数，返回渲染的统计信息。
+/// 设置了检查点时按照每轮的采样数分成多轮渲染，每轮结束和超过保存间隔的时候保存检查点。
+pub fn render(world: &dyn Hit, camera: &Camera, settings: &Settings, film: &mut Film) -> Stats {
+    let tiles = tiles(settings.width, settings.height);
+    let checkpoint = settings.checkpoint.as_ref();
+    // 自适应采样每一轮检查一次收敛，每轮的采样数固定，保证分轮的方式和检查点的设置无关
+    let batch = match &settings.adaptive {
+        Some(adaptive) => adaptive.min_samples,
+        None => checkpoint
+            .and_then(|c| c.samples)
+            .unwrap_or(settings.samples),
+    };
+
+    let seed = film.seed;
+    let total = film
+        .counts
+        .iter()
+        .map(|&count| settings.samples.saturating_sub(count))
+        .sum();
+    let progress = Progress::new(settings.progress, total);
+    let start = Instant::now();
+    let stats = Mutex::new(Stats {
+        width: settings.width,
+        height: settings.height,
+        threads: settings.threads.max(1),
+        ..Stats::default()
+    });
+    let film = Mutex::new(film);
+    let saved = Mutex::new(Instant::now());
+
+    // 每一轮把所有像素的采样数增加到 target
+    let mut target = film.lock().unwrap().samples();
+    while target < settings.samples {
+        target = (target / batch + 1) * batch;
+        target = target.min(settings.samples);
+        if settings.progress {
+            progress.message(&format!(
+                "Rendering up to {target} of {} samples",
+                settings.samples
+            ));
+        }
+
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..settings.threads.max(1) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else { break };
+
+                    let pixels = tile_pixels(&film.lock().unwrap(), tile);
+                    let started = Instant::now();
+                    let (added, counters) =
+                        render_tile(world, camera, settings, seed, tile, &pixels, target);
+                    let time = started.elapsed();
+
+                    let mut film = film.lock().unwrap();
+                    add_tile(&mut film, tile, &added);
+
+                    let mut stats = stats.lock().unwrap();
+                    stats.tiles += 1;
+                    stats.tile_time += time;
+                    stats.slowest_tile = stats.slowest_tile.max(time);
+                    drop(stats);
+                    progress.add(counters);
+
+                    let mut saved = saved.lock().unwrap();
+                    if checkpoint.is_some_and(|c| saved.elapsed() >= c.interval) {
+                        save(&film, checkpoint.unwrap(), &progress);
+                        *saved = Instant::now();
+                    }
+                });
+            }
+        });
+
+        if let Some(checkpoint) = checkpoint {
+            save(&film.lock().unwrap(), checkpoint, &progress);
+            *saved.lock().unwrap() = Instant::now();
+        }
+    }
+
+    let mut stats = stats.into_inner().unwrap();
+    stats.counters = progress.finish();
+    stats.elapsed = start.elapsed();
+    stats
+}
+
+// 一块区域里每个像素已经累加的结果
+fn tile_pixels(film: &Film, tile: &Tile) -> Vec<Pixel> {
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+    for row in tile.y..tile.y + tile.height {
+        let start = (row * film.width + tile.x) as usize;
+        pixels.extend((start..start + tile.width as usize).map(|index| Pixel {
+            color: film.colors[index],
+            square: film.squares[index],
+            count: film.counts[index],
+            features: film.features.as_ref().map(|_| aov::Sum::default()),
+        }));
+    }
+    pixels
+}
+
+// 把一块区域新增的采样累加到 film 里
+fn add_tile(film: &mut Film, tile: &Tile, added: &[Pixel]) {
+    for (row, line) in added.chunks(tile.width as usize).enumerate() {
+        let start = ((tile.y + row as u64) * film.width + tile.x) as usize;
+        for (offset, pixel) in line.iter().enumerate() {
+            film.colors[start + offset] += pixel.color;
+            film.squares[start + offset] += pixel.square;
+            film.counts[start + offset] += pixel.count;
+            if let (Some(features), Some(added)) = (&mut film.features, pixel.features) {
+                features[start + offset] += added;
+            }
+        }
+    }
+}
+
+// 保存失败不中断渲染，下一次还会再尝试
+fn save(film: &Film, checkpoint: &Checkpoint, progress: &Progress) {
+    match film.save(&checkpoint.path) {
+        Ok(()) if progress.enabled() => {
+            progress.message(&format!("Checkpoint saved to {}", checkpoint.path))
+        }
+        Ok(()) => {}
+        Err(error) => progress.message(&format!(
+            "failed to save checkpoint {}: {error}",
+            checkpoint.path
+        )),
+    }
+}
+
+fn tiles(width: u64, height: u64) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(TILE_SIZE as usize) {
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+// 渲染一块区域，每个像素从已有的采样数继续采样到 target，返回新增的采样和计数。
+// 自适应采样时已经收敛的像素跳过。
+fn render_tile(
+    world: &dyn Hit,
+    camera: &Camera,
+    settings: &Settings,
+    seed: u64,
+    tile: &Tile,
+    pixels: &[Pixel],
+    target: u64,
+) -> (Vec<Pixel>, Counters) {
+    let (width, height) = (settings.width, settings.height);
+    let mut counters = Counters::default();
+    // 丢掉这块开始之前其他地方留下的计数
+    stats::take_tests();
+    let mut added = Vec::with_capacity((tile.width * tile.height) as usize);
+    let mut pixels = pixels.iter();
+
+    for row in tile.y..tile.y + tile.height {
+        // 图片的第一行对应相机视口的最上面
+        let j = height - 1 - row;
+        for i in tile.x..tile.x + tile.width {
+            let current = pixels.next().unwrap();
+            let mut pixel = Pixel {
+                features: current.features,
+                ..Pixel::default()
+            };
+            if settings
+                .adaptive
+                .as_ref()
+                .is_some_and(|a| a.converged(current))
+            {
+                added.push(pixel);
+                continue;
+            }
+
+            let pixel_seed = pixel_seed(seed, row * width + i);
+            for sample in current.count..target {
+                // 每次采样单独设置种子，保证多线程、分多轮和从检查点恢复的结果都一致
+                let mut sampler = Sampler::new(sampler::mix(pixel_seed ^ sampler::mix(sample)));
+
+                // 像素 i 覆盖视口的 [i / width, (i + 1) / width)，只有一个像素的图片也可以渲染
+                let u = ((i as f64) + sampler.gen()) / width as f64;
+                let v = ((j as f64) + sampler.gen()) / height as f64;
+
+                let ray = camera.get_ray(u, v, &mut sampler);
+                let features = pixel.features.as_mut();
+                let color = ray_color(ray, world, settings, &mut sampler, &mut counters, features);
+                pixel.color += color;
+                pixel.square += color.luminance().powi(2);
+                pixel.count += 1;
+            }
+            counters.samples += pixel.count;
+            added.push(pixel);
+        }
+    }
+    counters.intersection_tests = stats::take_tests();
+    (added, counters)
+}
+
+/// 把全局种子和像素的序号混合成像素的种子
+pub fn pixel_seed(seed: u64, index: u64) -> u64 {
+    sampler::mix(seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+}
+
+// 多重重要性采样的 power heuristic，a 是当前采样方法的概率密度，b 是另一种的
+fn power_heuristic(a: f64, b: f64) -> f64 {
+    // 另一种方法不可能采样到这个方向
+    if b == 0.0 {
+        return 1.0;
+    }
+    a * a / (a * a + b * b)
+}
+
+// 可以直接采样的光源的个数，环境贴图也算一个
+fn light_count(settings: &Settings) -> usize {
+    settings.lights.len() + settings.environment.is_some() as usize
+}
+
+// 从所有光源里随机选一个采样，概率密度是每个光源的平均
+fn lights_pdf(settings: &Settings, origin: Point3, direction: Vector3) -> f64 {
+    let sum: f64 = settings
+        .lights
+        .iter()
+        .map(|light| light.pdf(origin, direction))
+        .sum();
+    let environment = settings
+        .environment
+        .as_ref()
+        .map_or(0.0, |environment| environment.pdf(direction));
+    (sum + environment) / light_count(settings) as f64
+}
+
+/// 光线的颜色计算，沿着路径循环，throughput 是路径到目前为止的衰减。
+/// 在漫反射的表面上同时对光源和材质采样（next event estimation），两种方法用 power heuristic 合并。
+/// 反射 ROULETTE_DEPTH 次之后按照 throughput 随机结束路径（俄罗斯轮盘赌），
+/// 活下来的路径除以存活的概率，期望不变；最多反射 max_depth 次。
+/// features 不是 None 的时候把相机射线第一次命中的信息累加进去。
+fn ray_color(
+    mut ray: Ray,
+    hittable: &dyn Hit,
+    settings: &Settings,
+    sampler: &mut Sampler,
+    counters: &mut Counters,
+    mut features: Option<&mut aov::Sum>,
+) -> Color {
+    let lights = light_count(settings);
+    let mut radiance = Color::default();
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    // 上一次按材质采样到这条射线方向的概率密度，
+    // 相机发出的射线和镜面反射、折射的射线是 None，打到发光物体的时候不需要合并
+    let mut pdf = None;
+    for depth in 0..settings.max_depth {
+        match depth {
+            0 => counters.primary_rays += 1,
+            _ => counters.bounce_rays += 1,
+        }
+        ray.seed = sampler.next_u64();
+        let hit = hittable.hit(&ray, 0.001, f64::INFINITY);
+        if let Some(features) = features.take() {
+            features.add_sample(&ray, hit.as_ref(), settings);
+        }
+        // 射线未命中，加上背景的颜色，对环境贴图采样的时候也可能采样到这个方向
+        let Some(record) = hit else {
+            let mut color = settings.background.color(&ray);
+            if let (Some(pdf), Some(_)) = (pdf, &settings.environment) {
+                color =
+                    power_heuristic(pdf, lights_pdf(settings, ray.origin, ray.direction)) * color;
+            }
+            radiance += throughput * color;
+            break;
+        };
+
+        // 命中发光的物体，加上物体本身发出的光，这个方向也可能是对光源采样得到的
+        let mut emitted = record.material.emitted(record.u, record.v, record.point);
+        if let (Some(pdf), true) = (pdf, lights > 0) {
+            emitted =
+                power_heuristic(pdf, lights_pdf(settings, ray.origin, ray.direction)) * emitted;
+        }
+        radiance += throughput * emitted;
+
+        // 命中物体根据材料散射光线
+        let Some((attenuation, scattered)) = record.material.scatter(&ray, &record, sampler) else {
+            break;
+        };
+        let material_pdf = record
+            .material
+            .scattering_pdf(&ray, &record, scattered.direction);
+
+        // 镜面反射和折射不对光源采样
+        if material_pdf.is_some() && lights > 0 {
+            radiance +=
+                throughput * direct_light(&ray, &record, hittable, settings, sampler, counters);
+        }
+
+        throughput = throughput * attenuation;
+        if depth + 1 >= ROULETTE_DEPTH {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if sampler.gen() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        ray = scattered;
+        pdf = material_pdf;
+    }
+    radiance
+}
+
+// 随机选一个光源，朝着它采样一个方向，只计算这个方向上直接发出的光
+fn direct_light(
+    ray: &Ray,
+    record: &HitRecord,
+    hittable: &dyn Hit,
+    settings: &Settings,
+    sampler: &mut Sampler,
+    counters: &mut Counters,
+) -> Color {
+    let lights = light_count(settings);
+    let index = ((sampler.gen() * lights as f64) as usize).min(lights - 1);
+    let direction = match (settings.lights.get(index), &settings.environment) {
+        (Some(light), _) => light.random_direction(record.point, sampler),
+        (None, Some(environment)) => environment.random_direction(sampler),
+        (None, None) => unreachable!(),
+    };
+    let light_pdf = lights_pdf(settings, record.point, direction);
+    if light_pdf <= 0.0 {
+        return Color::default();
+    }
+    let shadow = Ray::new(record.point, direction, ray.time).with_seed(sampler);
+    counters.shadow_rays += 1;
+    let material_pdf = record
+        .material
+        .scattering_pdf(ray, record, direction)
+        .unwrap_or(0.0);
+    let radiance = match (
+        hittable.hit(&shadow, 0.001, f64::INFINITY),
+        &settings.environment,
+    ) {
+        (Some(hit), _) => hit.material.emitted(hit.u, hit.v, hit.point),
+        (None, Some(_)) => settings.background.color(&shadow),
+        (None, None) => Color::default(),
+    };
+    let weight = power_heuristic(light_pdf, material_pdf) / light_pdf;
+    weight * record.material.evaluate(ray, record, direction) * radiance
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::color::ToneMap;
+    use crate::{bvh, scene};
+
+    // 用两个不同的种子渲染同一个场景，两张图片的均方差反映了噪点的大小
+    fn noise(light_sampling: bool) -> (f64, f64) {
+        let render_with = |seed| {
+            let text = include_str!("../scenes/small-light.toml");
+            let scene = scene::parse(text, Path::new("")).unwrap();
+            let (width, height) = (24, 16);
+            let camera = scene.camera.camera(1.5);
+            let settings = Settings {
+                width,
+                height,
+                samples: 16,
+                max_depth: 4,
+                background: scene.background,
+                lights: match light_sampling {
+                    true => scene.lights,
+                    false => Lights::new(),
+                },
+                threads: 1,
+                progress: false,
+                checkpoint: None,
+                environment: None,
+                adaptive: None,
+            };
+            let mut film = Film::new(width, height, seed);
+            render(&bvh::accelerate(scene.world), &camera, &settings, &mut film);
+            film.colors
+                .iter()
+                .map(|color| (color.x + color.y + color.z) / 16.0)
+                .collect::<Vec<_>>()
+        };
+
+        let (a, b) = (render_with(1), render_with(2));
+        let count = a.len() as f64;
+        let mean = a.iter().chain(&b).sum::<f64>() / (2.0 * count);
+        let error = a.iter().zip(&b).map(|(x, y)| (x - y).powi(2)).sum::<f64>() / count;
+        (mean, error)
+    }
+
+    // 原来的递归版本，每次都反射到 max_depth 为止，作为参考
+    fn recursive_ray_color(
+        ray: &Ray,
+        hittable: &dyn Hit,
+        settings: &Settings,
+        depth: u64,
+        sampler: &mut Sampler,
+        pdf: Option<f64>,
+    ) -> Color {
+        if depth == 0 {
+            return Color::default();
+        }
+        let lights = light_count(settings);
+        let Some(record) = hittable.hit(ray, 0.001, f64::INFINITY) else {
+            let color = settings.background.color(ray);
+            return match (pdf, &settings.environment) {
+                (Some(pdf), Some(_)) => {
+                    power_heuristic(pdf, lights_pdf(settings, ray.origin, ray.direction)) * color
+                }
+                _ => color,
+            };
+        };
+        let mut emitted = record.material.emitted(record.u, record.v, record.point);
+        if let (Some(pdf), true) = (pdf, lights > 0) {
+            emitted =
+                power_heuristic(pdf, lights_pdf(settings, ray.origin, ray.direction)) * emitted;
+        }
+        let Some((attenuation, scattered)) = record.material.scatter(ray, &record, sampler) else {
+            return emitted;
+        };
+        let material_pdf = record
+            .material
+            .scattering_pdf(ray, &record, scattered.direction);
+        let indirect = recursive_ray_color(
+            &scattered,
+            hittable,
+            settings,
+            depth - 1,
+            sampler,
+            material_pdf,
+        );
+        let direct = match material_pdf.is_some() && lights > 0 {
+            true => {
+                let counters = &mut Counters::default();
+                direct_light(ray, &record, hittable, settings, sampler, counters)
+            }
+            false => Color::default(),
+        };
+        emitted + direct + attenuation * indirect
+    }
+
+    #[test]
+    fn russian_roulette_matches_recursive_mean() {
+        let scene = scene::builtin("cornell", &mut Sampler::new(0)).unwrap();
+        let camera = scene.camera.camera(1.0);
+        let world = bvh::accelerate(scene.world);
+        let settings = Settings {
+            width: 1,
+            height: 1,
+            samples: 1,
+            max_depth: 16,
+            background: scene.background,
+            lights: scene.lights,
+            environment: None,
+            threads: 1,
+            progress: false,
+            checkpoint: None,
+            adaptive: None,
+        };
+
+        // 在整个画面上随机取点，两种方法的平均亮度应该相同
+        let count = 20_000;
+        let (mut iterative, mut recursive) = (Color::default(), Color::default());
+        let mut sampler = Sampler::new(3);
+        let mut counters = Counters::default();
+        for _ in 0..count {
+            let (u, v) = (sampler.gen(), sampler.gen());
+            let ray = camera.get_ray(u, v, &mut sampler);
+            iterative += ray_color(ray, &world, &settings, &mut sampler, &mut counters, None);
+            let ray = camera.get_ray(u, v, &mut sampler);
+            let depth = settings.max_depth;
+            recursive += recursive_ray_color(&ray, &world, &settings, depth, &mut sampler, None);
+        }
+        for axis in 0..3 {
+            let (a, b) = (
+                iterative[axis] / count as f64,
+                recursive[axis] / count as f64,
+            );
+            assert!((a - b).abs() < 0.05 * b, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn single_pixel_images_render() {
+        let scene = scene::builtin("cornell", &mut Sampler::new(0)).unwrap();
+        let camera = scene.camera.camera(1.0);
+        let world = bvh::accelerate(scene.world);
+        let settings = Settings {
+            width: 1,
+            height: 1,
+            samples: 16,
+            max_depth: 8,
+            background: scene.background,
+            lights: scene.lights,
+            environment: None,
+            threads: 1,
+            progress: false,
+            checkpoint: None,
+            adaptive: None,
+        };
+        let mut film = Film::new(1, 1, 1);
+        render(&world, &camera, &settings, &mut film);
+        // 整个视口的平均颜色，不能是 NaN 或者无穷大
+        let color = film.colors[0] / 16.0;
+        assert!(color.x.is_finite() && color.y.is_finite() && color.z.is_finite());
+        assert!(color.luminance() > 0.0);
+    }
+
+    #[test]
+    fn stats_count_every_sample() {
+        let scene = scene::builtin("cornell", &mut Sampler::new(0)).unwrap();
+        let (width, height) = (40, 40);
+        let camera = scene.camera.camera(1.0);
+        let settings = Settings {
+            width,
+            height,
+            samples: 4,
+            max_depth: 8,
+            background: scene.background,
+            lights: scene.lights,
+            environment: None,
+            threads: 2,
+            progress: false,
+            checkpoint: None,
+            adaptive: None,
+        };
+        let mut film = Film::new(width, height, 1);
+        let stats = render(&bvh::accelerate(scene.world), &camera, &settings, &mut film);
+
+        let c = &stats.counters;
+        assert_eq!(c.samples, width * height * 4);
+        assert_eq!(c.primary_rays, c.samples);
+        assert!(c.bounce_rays > 0 && c.shadow_rays > 0);
+        assert!(c.intersection_tests > c.rays());
+        assert!((1.0..=8.0).contains(&stats.path_length()));
+        assert_eq!(stats.tiles, 4);
+        assert!(stats.slowest_tile <= stats.tile_time);
+        assert!(stats
+            .json()
+            .contains(&format!("\"primary\": {}", c.primary_rays)));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_result() {
+        // 宽高不是分块边长的整数倍，自适应采样让各个像素的采样数不同
+        let render_with = |threads| {
+            let scene = scene::builtin("cornell", &mut Sampler::new(0)).unwrap();
+            let (width, height) = (70, 40);
+            let camera = scene.camera.camera(70.0 / 40.0);
+            let settings = Settings {
+                width,
+                height,
+                samples: 6,
+                max_depth: 6,
+                background: scene.background,
+                lights: scene.lights,
+                environment: None,
+                threads,
+                progress: false,
+                checkpoint: None,
+                adaptive: Some(Adaptive {
+                    min_samples: 2,
+                    threshold: 0.05,
+                    display: Display::default(),
+                }),
+            };
+            let mut film = Film::new(width, height, 7);
+            render(&bvh::accelerate(scene.world), &camera, &settings, &mut film);
+            film
+        };
+
+        let (single, multiple) = (render_with(1), render_with(4));
+        let bits = |film: &Film| {
+            film.colors
+                .iter()
+                .zip(&film.squares)
+                .map(|(color, square)| [color.x, color.y, color.z, *square].map(f64::to_bits))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(single.counts, multiple.counts);
+        assert!(bits(&single) == bits(&multiple));
+    }
+
+    #[test]
+    fn adaptive_error_uses_display_transform() {
+        // 16 次采样，亮度一半是 mean - d，一半是 mean + d
+        let pixel = |mean: f64, d: f64| Pixel {
+            color: Color::new(mean, mean, mean) * 16.0,
+            square: 8.0 * ((mean - d).powi(2) + (mean + d).powi(2)),
+            count: 16,
+            features: None,
+        };
+        let adaptive = |exposure, tone_map| Adaptive {
+            min_samples: 4,
+            threshold: 0.01,
+            display: Display { exposure, tone_map },
+        };
+        let plain = adaptive(0.0, ToneMap::Clamp);
+
+        // 标准误差大约是 0.0026，中等亮度的时候显示出来大约 0.003
+        assert!(plain.converged(&pixel(0.2, 0.01)));
+        assert!(plain.converged(&pixel(0.05, 0.01)));
+        // 同样的误差在暗部经过 sRGB 编码被放大
+        assert!(!plain.converged(&pixel(0.002, 0.01)));
+        // 增加曝光之后误差变大
+        assert!(!adaptive(3.0, ToneMap::Clamp).converged(&pixel(0.05, 0.01)));
+        // 截断成白色的像素噪点再大也看不出来，色调映射之后仍然看得出来
+        assert!(plain.converged(&pixel(2.0, 1.0)));
+        assert!(!adaptive(0.0, ToneMap::Reinhard).converged(&pixel(2.0, 1.0)));
+    }
+
+    #[test]
+    fn light_sampling_reduces_noise() {
+        let (mis_mean, mis_error) = noise(true);
+        let (bsdf_mean, bsdf_error) = noise(false);
+
+        // 两种方法的期望相同，相同的采样数下对光源采样的噪点要小得多
+        assert!(mis_mean > 0.0);
+        assert!((mis_mean - bsdf_mean).abs() < 0.5 * mis_mean);
+        assert!(
+            mis_error * 100.0 < bsdf_error,
+            "{mis_error} vs {bsdf_error}"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::ops::Range;
+
+// 随机数生成器（xoshiro256++），由调用者显式地传递。
+// 不依赖 rand 的 StdRng，因为它的算法在不同版本之间可能会变，相同的种子必须一直得到相同的图片。
+pub struct Sampler {
+    state: [u64; 4],
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        // 使用 SplitMix64 把种子扩展成内部状态
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            mix(seed)
+        };
+        Sampler {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    /// 64 位的随机整数
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = (s[0].wrapping_add(s[3])).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// [0, 1) 之间的随机数
+    pub fn gen(&mut self) -> f64 {
+        // 取高 53 位作为双精度浮点数的尾数
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    pub fn gen_range(&mut self, range: Range<f64>) -> f64 {
+        range.start + (range.end - range.start) * self.gen()
+    }
+}
+
+/// SplitMix64 的混合函数，也用来把种子和像素的序号混合成像素的种子
+pub fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
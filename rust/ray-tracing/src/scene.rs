@@ -0,0 +1,810 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::animation::{Animation, Keyframe};
+use crate::background::{self, Background};
+use crate::camera::Camera;
+use crate::color::Display;
+use crate::environment::Environment;
+use crate::hdr::HdrImage;
+use crate::hittable::{Hit, Lights, World};
+use crate::image::Image;
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambert, Material, Metal};
+use crate::matrix::Matrix4;
+use crate::medium::ConstantMedium;
+use crate::mesh::{Mesh, Placement};
+use crate::noise::{Marble, Wood};
+use crate::quad::{Cuboid, Plane, Quad};
+use crate::sampler::Sampler;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{Checker, ImageTexture, SolidColor, Texture};
+use crate::toml::{self, Error, Kind, Table, Value};
+use crate::transform::Transform;
+use crate::vector3::{Color, Point3, Vector3};
+
+// 场景：相机、图片设置、背景和所有的物体
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub image: ImageSettings,
+    pub background: Background,
+    pub world: World,
+    // 发光的球和四边形，渲染的时候直接对它们采样
+    pub lights: Lights,
+    // 相机的关键帧动画，没有的时候只渲染一张图片
+    pub animation: Option<Animation>,
+}
+
+pub struct CameraSettings {
+    pub origin: Point3,
+    pub at: Point3,
+    pub vup: Vector3,
+    pub fov: f64,
+    pub aperture: f64,
+    pub focus: f64,
+    // 快门打开和关闭的时刻，相同的时候没有运动模糊
+    pub shutter: (f64, f64),
+}
+
+impl CameraSettings {
+    pub fn camera(&self, ratio: f64) -> Camera {
+        Camera::new(
+            self.origin,
+            self.at,
+            self.vup,
+            self.fov,
+            ratio,
+            self.aperture,
+            self.focus,
+        )
+        .with_shutter(self.shutter.0, self.shutter.1)
+    }
+}
+
+pub struct ImageSettings {
+    pub width: u64,
+    // 宽高比
+    pub ratio: f64,
+    pub samples: u64,
+    pub max_depth: u64,
+    pub display: Display,
+}
+
+impl ImageSettings {
+    pub fn height(&self) -> u64 {
+        ((self.width as f64) / self.ratio) as u64
+    }
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        ImageSettings {
+            width: 1200,
+            ratio: 3.0 / 2.0,
+            samples: 500,
+            max_depth: 50,
+            display: Display::default(),
+        }
+    }
+}
+
+/// 从文件加载场景，错误信息里包含行号
+pub fn load(path: &str) -> Result<Scene, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::new(0, e.to_string()))?;
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse(&text, base)
+}
+
+/// 解析场景，场景里引用的文件相对于 base 目录
+pub fn parse(text: &str, base: &Path) -> Result<Scene, Error> {
+    let root = toml::parse(text)?;
+
+    let image = match root.get("image") {
+        Some(value) => parse_image(value.as_table()?)?,
+        None => ImageSettings::default(),
+    };
+    let camera = parse_camera(root.required("camera")?.as_table()?)?;
+    let animation = match root.get("animation") {
+        Some(value) => Some(parse_animation(value.as_table()?, &camera)?),
+        None => None,
+    };
+    let background = match root.get("background") {
+        Some(value) => parse_background(value.as_table()?, base)?,
+        None => Background::default(),
+    };
+
+    let mut textures = HashMap::new();
+    if let Some(value) = root.get("textures") {
+        for (name, value) in value.as_table()?.iter() {
+            textures.insert(name.as_str(), parse_texture(value.as_table()?, base)?);
+        }
+    }
+
+    let mut materials = HashMap::new();
+    if let Some(value) = root.get("materials") {
+        for (name, value) in value.as_table()?.iter() {
+            materials.insert(name.as_str(), parse_material(value.as_table()?, &textures)?);
+        }
+    }
+
+    let mut world = World::new();
+    let mut lights = Lights::new();
+    // 同一个网格文件只加载一次，多个实例共享同一份几何数据
+    let mut meshes = HashMap::new();
+    if let Some(value) = root.get("objects") {
+        for value in value.as_array()? {
+            let table = value.as_table()?;
+            let kind = table.required("type")?;
+            let object = parse_object(kind, table, &materials, &mut meshes, base)?;
+            match table.get("transform") {
+                Some(value) => {
+                    let transform = Transform::new(object, parse_transform(value)?)
+                        .ok_or_else(|| Error::new(value.line, "transform is not invertible"))?;
+                    world.push(Box::new(transform));
+                }
+                None => {
+                    let emissive = match table.get("material") {
+                        Some(name) => material(&materials, name)?.is_emissive(),
+                        None => false,
+                    };
+                    // `light = false` 的发光物体不直接采样
+                    let light = table.get("light").map_or(Ok(true), Value::as_bool)?;
+                    if emissive && light && matches!(kind.as_str()?, "Sphere" | "Quad") {
+                        lights.push(Arc::clone(&object));
+                    }
+                    world.push(Box::new(object));
+                }
+            }
+        }
+    }
+
+    Ok(Scene {
+        camera,
+        image,
+        background,
+        world,
+        lights,
+        animation,
+    })
+}
+
+// 网格按照文件、材质和摆放的位置缓存
+type MeshKey = (String, Option<String>, [u64; 4]);
+
+/// kind 是物体的类型，参与介质的边界使用同一个表里的参数，只是类型不同
+fn parse_object(
+    kind: &Value,
+    table: &Table,
+    materials: &HashMap<&str, Arc<dyn Material>>,
+    meshes: &mut HashMap<MeshKey, Arc<dyn Hit>>,
+    base: &Path,
+) -> Result<Arc<dyn Hit>, Error> {
+    let object: Arc<dyn Hit> = match kind.as_str()? {
+        "Sphere" => {
+            let center = vector3(table.required("center")?)?;
+            let radius = table.required("radius")?.as_f64()?;
+            let material = material(materials, table.required("material")?)?;
+            Arc::new(Sphere::new(center, radius, material))
+        }
+        "MovingSphere" => {
+            let center0 = vector3(table.required("center0")?)?;
+            let center1 = vector3(table.required("center1")?)?;
+            let time0 = table.get("time0").map_or(Ok(0.0), Value::as_f64)?;
+            let time1 = table.get("time1").map_or(Ok(1.0), Value::as_f64)?;
+            let radius = table.required("radius")?.as_f64()?;
+            let material = material(materials, table.required("material")?)?;
+            Arc::new(MovingSphere::new(
+                center0, center1, time0, time1, radius, material,
+            ))
+        }
+        "Quad" => {
+            let corner = vector3(table.required("corner")?)?;
+            let u = vector3(table.required("u")?)?;
+            let v = vector3(table.required("v")?)?;
+            let material = material(materials, table.required("material")?)?;
+            Arc::new(Quad::new(corner, u, v, material))
+        }
+        "Plane" => {
+            let point = vector3(table.required("point")?)?;
+            let normal = vector3(table.required("normal")?)?;
+            let material = material(materials, table.required("material")?)?;
+            Arc::new(Plane::new(point, normal, material))
+        }
+        "Cuboid" => {
+            let min = vector3(table.required("min")?)?;
+            let max = vector3(table.required("max")?)?;
+            let material = material(materials, table.required("material")?)?;
+            Arc::new(Cuboid::new(min, max, material))
+        }
+        "Mesh" => {
+            let path = table.required("path")?;
+            let placement = Placement {
+                position: match table.get("position") {
+                    Some(value) => vector3(value)?,
+                    None => Vector3::default(),
+                },
+                scale: match table.get("scale") {
+                    Some(value) => value.as_f64()?,
+                    None => 1.0,
+                },
+            };
+            let name = table.get("material").map(Value::as_str).transpose()?;
+            let position = placement.position;
+            let key = (
+                path.as_str()?.to_string(),
+                name.map(str::to_string),
+                [position.x, position.y, position.z, placement.scale].map(f64::to_bits),
+            );
+            if let Some(mesh) = meshes.get(&key) {
+                return Ok(Arc::clone(mesh));
+            }
+
+            // 没有在 .mtl 里指定材质的面使用这个材质
+            let material = match table.get("material") {
+                Some(value) => material(materials, value)?,
+                None => Arc::new(Lambert::new(Color::new(0.8, 0.8, 0.8))),
+            };
+            let mesh: Arc<dyn Hit> = Arc::new(
+                Mesh::load(&base.join(path.as_str()?), material, &placement)
+                    .map_err(|message| Error::new(path.line, message))?,
+            );
+            meshes.insert(key, Arc::clone(&mesh));
+            mesh
+        }
+        "ConstantMedium" => {
+            let boundary = table.required("boundary")?;
+            if boundary.as_str()? == "ConstantMedium" {
+                return Err(Error::new(boundary.line, "a medium cannot bound a medium"));
+            }
+            let shape = parse_object(boundary, table, materials, meshes, base)?;
+            let density = table.required("density")?;
+            let density = number(density, |x| x.is_finite() && x > 0.0, "positive")?;
+            let phase = material(materials, table.required("material")?)?;
+            Arc::new(ConstantMedium::new(shape, density, phase))
+        }
+        other => return Err(Error::new(kind.line, format!("unknown object `{other}`"))),
+    };
+    Ok(object)
+}
+
+// 变换的列表，按顺序作用在物体上，比如先缩放、再旋转、最后平移
+fn parse_transform(value: &Value) -> Result<Matrix4, Error> {
+    let mut matrix = Matrix4::identity();
+    for step in value.as_array()? {
+        let table = step.as_table()?;
+        let next = match (
+            table.get("translate"),
+            table.get("rotate"),
+            table.get("scale"),
+        ) {
+            (Some(offset), None, None) => Matrix4::translation(vector3(offset)?),
+            (None, Some(axis), None) => {
+                Matrix4::rotation(vector3(axis)?, table.required("angle")?.as_f64()?)
+            }
+            (None, None, Some(factor)) => match factor.kind {
+                // 一个数表示均匀缩放
+                Kind::Integer(_) | Kind::Float(_) => {
+                    let factor = factor.as_f64()?;
+                    Matrix4::scaling(Vector3::new(factor, factor, factor))
+                }
+                _ => Matrix4::scaling(vector3(factor)?),
+            },
+            _ => {
+                return Err(Error::new(
+                    step.line,
+                    "expected one of `translate`, `rotate` or `scale`",
+                ))
+            }
+        };
+        matrix = next * matrix;
+    }
+    Ok(matrix)
+}
+
+fn parse_background(table: &Table, base: &Path) -> Result<Background, Error> {
+    let kind = table.required("type")?;
+    match kind.as_str()? {
+        "Gradient" => {
+            // 没有指定的颜色使用默认的天空
+            Ok(Background::Gradient {
+                bottom: table
+                    .get("bottom")
+                    .map_or(Ok(background::SKY_BOTTOM), vector3)?,
+                top: table.get("top").map_or(Ok(background::SKY_TOP), vector3)?,
+            })
+        }
+        "Solid" => Ok(Background::Solid(vector3(table.required("color")?)?)),
+        "Black" => Ok(Background::black()),
+        "Environment" => {
+            let path = table.required("path")?;
+            let image = HdrImage::load(&base.join(path.as_str()?))
+                .map_err(|message| Error::new(path.line, message))?;
+            if image.width == 0 || image.height == 0 {
+                return Err(Error::new(path.line, "the environment map is empty"));
+            }
+            let rotation = match table.get("rotation") {
+                Some(value) => value.as_f64()?,
+                None => 0.0,
+            };
+            let intensity = match table.get("intensity") {
+                Some(value) => number(value, |x| x.is_finite() && x >= 0.0, "non-negative")?,
+                None => 1.0,
+            };
+            let environment = Environment::new(image, rotation, intensity);
+            Ok(Background::Environment(Arc::new(environment)))
+        }
+        other => Err(Error::new(
+            kind.line,
+            format!("unknown background `{other}`"),
+        )),
+    }
+}
+
+fn parse_image(table: &Table) -> Result<ImageSettings, Error> {
+    let mut image = ImageSettings::default();
+    if let Some(value) = table.get("width") {
+        image.width = value.as_u64()?;
+    }
+    if let Some(value) = table.get("ratio") {
+        image.ratio = value.as_f64()?;
+    }
+    if let Some(value) = table.get("samples") {
+        image.samples = value.as_u64()?;
+    }
+    if let Some(value) = table.get("max_depth") {
+        image.max_depth = value.as_u64()?;
+    }
+    if let Some(value) = table.get("exposure") {
+        image.display.exposure = number(value, Display::valid_exposure, "valid exposure")?;
+    }
+    if let Some(value) = table.get("tone_map") {
+        image.display.tone_map = value
+            .as_str()?
+            .parse()
+            .map_err(|message| Error::new(value.line, message))?;
+    }
+    Ok(image)
+}
+
+fn parse_camera(table: &Table) -> Result<CameraSettings, Error> {
+    let origin = vector3(table.required("origin")?)?;
+    let at = vector3(table.required("at")?)?;
+    let vup = match table.get("vup") {
+        Some(value) => vector3(value)?,
+        None => Vector3::new(0.0, 1.0, 0.0),
+    };
+    let aperture = match table.get("aperture") {
+        Some(value) => value.as_f64()?,
+        None => 0.0,
+    };
+    // 没有指定焦距的时候对焦到观察点
+    let focus = match table.get("focus") {
+        Some(value) => value.as_f64()?,
+        None => (origin - at).length(),
+    };
+    let shutter = match table.get("shutter") {
+        Some(value) => match value.as_array()? {
+            [open, close] => (open.as_f64()?, close.as_f64()?),
+            _ => return Err(Error::new(value.line, "expected an array of 2 numbers")),
+        },
+        None => (0.0, 0.0),
+    };
+
+    Ok(CameraSettings {
+        origin,
+        at,
+        vup,
+        fov: table.required("fov")?.as_f64()?,
+        aperture,
+        focus,
+        shutter,
+    })
+}
+
+// 关键帧里没有写的参数使用 [camera] 的设置，焦距默认对焦到这一帧的观察点
+fn parse_animation(table: &Table, camera: &CameraSettings) -> Result<Animation, Error> {
+    let interpolation = match table.get("interpolation") {
+        Some(value) => value
+            .as_str()?
+            .parse()
+            .map_err(|message| Error::new(value.line, message))?,
+        None => Default::default(),
+    };
+
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+    let value = table.required("keyframes")?;
+    for value in value.as_array()? {
+        let key = value.as_table()?;
+        let frame = key.required("frame")?;
+        let origin = key.get("origin").map_or(Ok(camera.origin), vector3)?;
+        let at = key.get("at").map_or(Ok(camera.at), vector3)?;
+        let number = |name, default| key.get(name).map_or(Ok(default), Value::as_f64);
+        let keyframe = Keyframe {
+            frame: frame.as_f64()?,
+            origin,
+            at,
+            fov: number("fov", camera.fov)?,
+            focus: number("focus", (origin - at).length())?,
+            aperture: number("aperture", camera.aperture)?,
+        };
+        if keyframes.iter().any(|other| other.frame == keyframe.frame) {
+            let message = format!("duplicate keyframe {}", keyframe.frame);
+            return Err(Error::new(frame.line, message));
+        }
+        keyframes.push(keyframe);
+    }
+    if keyframes.is_empty() {
+        return Err(Error::new(
+            value.line,
+            "an animation needs at least one keyframe",
+        ));
+    }
+
+    // 默认渲染第一个到最后一个关键帧
+    let mut animation = Animation::new(keyframes, interpolation);
+    if let Some(value) = table.get("frames") {
+        match value.as_array()? {
+            [start, end] if start.as_u64()? <= end.as_u64()? => {
+                (animation.start, animation.end) = (start.as_u64()?, end.as_u64()?);
+            }
+            _ => return Err(Error::new(value.line, "expected [start, end] frames")),
+        }
+    }
+    Ok(animation)
+}
+
+fn parse_texture(table: &Table, base: &Path) -> Result<Arc<dyn Texture>, Error> {
+    let kind = table.required("type")?;
+    let texture: Arc<dyn Texture> = match kind.as_str()? {
+        "Solid" => Arc::new(SolidColor::new(vector3(table.required("color")?)?)),
+        "Checker" => {
+            let scale = number(table.required("scale")?, f64::is_normal, "non-zero")?;
+            let even = SolidColor::new(vector3(table.required("even")?)?);
+            let odd = SolidColor::new(vector3(table.required("odd")?)?);
+            Arc::new(Checker::new(scale, Arc::new(even), Arc::new(odd)))
+        }
+        "Image" => {
+            let path = table.required("path")?;
+            let image = Image::load(&base.join(path.as_str()?))
+                .map_err(|message| Error::new(path.line, message))?;
+            Arc::new(ImageTexture::new(&image))
+        }
+        "Marble" => {
+            let base = color_or(table, "base", Color::new(1.0, 1.0, 1.0))?;
+            let vein = color_or(table, "vein", Color::new(0.0, 0.0, 0.0))?;
+            let (seed, scale) = (noise_seed(table)?, table.required("scale")?.as_f64()?);
+            Arc::new(Marble::new(seed, scale, base, vein))
+        }
+        "Wood" => {
+            let light = color_or(table, "light", Color::new(0.75, 0.55, 0.33))?;
+            let dark = color_or(table, "dark", Color::new(0.4, 0.24, 0.12))?;
+            let (seed, scale) = (noise_seed(table)?, table.required("scale")?.as_f64()?);
+            Arc::new(Wood::new(seed, scale, light, dark))
+        }
+        other => return Err(Error::new(kind.line, format!("unknown texture `{other}`"))),
+    };
+    Ok(texture)
+}
+
+fn parse_material(
+    table: &Table,
+    textures: &HashMap<&str, Arc<dyn Texture>>,
+) -> Result<Arc<dyn Material>, Error> {
+    let kind = table.required("type")?;
+    let material: Arc<dyn Material> = match kind.as_str()? {
+        "Lambert" => Arc::new(Lambert::with_texture(texture(
+            textures,
+            table.required("albedo")?,
+        )?)),
+        "Metal" => {
+            let albedo = texture(textures, table.required("albedo")?)?;
+            let fuzz = match table.get("fuzz") {
+                Some(value) => value.as_f64()?,
+                None => 0.0,
+            };
+            Arc::new(Metal::with_texture(albedo, fuzz))
+        }
+        "Dielectric" => Arc::new(Dielectric::new(table.required("refraction")?.as_f64()?)),
+        "Isotropic" => {
+            let albedo = texture(textures, table.required("albedo")?)?;
+            let anisotropy = table.get("anisotropy").map_or(Ok(0.0), Value::as_f64)?;
+            Arc::new(Isotropic::with_texture(albedo).with_anisotropy(anisotropy))
+        }
+        "DiffuseLight" => Arc::new(DiffuseLight::with_texture(texture(
+            textures,
+            table.required("color")?,
+        )?)),
+        other => return Err(Error::new(kind.line, format!("unknown material `{other}`"))),
+    };
+    Ok(material)
+}
+
+fn color_or(table: &Table, key: &str, default: Color) -> Result<Color, Error> {
+    table.get(key).map_or(Ok(default), vector3)
+}
+
+// 噪声纹理的种子，相同的种子得到相同的花纹
+fn noise_seed(table: &Table) -> Result<u64, Error> {
+    table.get("seed").map_or(Ok(0), Value::as_u64)
+}
+
+// 颜色可以直接写成数组，也可以引用 [textures] 里的纹理
+fn texture(
+    textures: &HashMap<&str, Arc<dyn Texture>>,
+    value: &Value,
+) -> Result<Arc<dyn Texture>, Error> {
+    if let Kind::Array(_) = value.kind {
+        return Ok(Arc::new(SolidColor::new(vector3(value)?)));
+    }
+    let name = value.as_str()?;
+    match textures.get(name) {
+        Some(texture) => Ok(Arc::clone(texture)),
+        None => Err(Error::new(value.line, format!("unknown texture `{name}`"))),
+    }
+}
+
+fn material(
+    materials: &HashMap<&str, Arc<dyn Material>>,
+    value: &Value,
+) -> Result<Arc<dyn Material>, Error> {
+    let name = value.as_str()?;
+    match materials.get(name) {
+        Some(material) => Ok(Arc::clone(material)),
+        None => Err(Error::new(value.line, format!("unknown material `{name}`"))),
+    }
+}
+
+// 数值满足 valid 的时候返回它，否则报告期望的是什么样的数
+fn number(value: &Value, valid: fn(f64) -> bool, expected: &str) -> Result<f64, Error> {
+    let number = value.as_f64()?;
+    match valid(number) {
+        true => Ok(number),
+        false => Err(Error::new(
+            value.line,
+            format!("expected a {expected} number, found {number}"),
+        )),
+    }
+}
+
+fn vector3(value: &Value) -> Result<Vector3, Error> {
+    match value.as_array()? {
+        [x, y, z] => Ok(Vector3::new(x.as_f64()?, y.as_f64()?, z.as_f64()?)),
+        _ => Err(Error::new(value.line, "expected an array of 3 numbers")),
+    }
+}
+
+/// 内置的场景，名字不存在的时候返回 None
+pub fn builtin(name: &str, sampler: &mut Sampler) -> Option<Scene> {
+    match name {
+        "random" => Some(random(sampler)),
+        "cornell" => Some(cornell(false)),
+        "cornell-smoke" => Some(cornell(true)),
+        _ => None,
+    }
+}
+
+/// 内置的随机场景，地面上有很多随机的小球和三个大球
+fn random(sampler: &mut Sampler) -> Scene {
+    let mut world = World::new();
+
+    let ground = Arc::new(Lambert::new(Color::new(0.5, 0.5, 0.5)));
+    let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground);
+
+    world.push(Box::new(ground));
+
+    for a in -11..=11 {
+        for b in -11..=11 {
+            let choose_mat = sampler.gen();
+            let center = Point3::new(
+                (a as f64) + sampler.gen_range(0.0..0.9),
+                0.2,
+                (b as f64) + sampler.gen_range(0.0..0.9),
+            );
+
+            if choose_mat < 0.8 {
+                let albedo = Color::random(0.0..1.0, sampler) * Color::random(0.0..1.0, sampler);
+                let sphere_mat = Arc::new(Lambert::new(albedo));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.push(Box::new(sphere));
+            } else if choose_mat < 0.95 {
+                let albedo = Color::random(0.4..1.0, sampler);
+                let fuzz = sampler.gen_range(0.0..0.5);
+                let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.push(Box::new(sphere));
+            } else {
+                // Glass
+                let sphere_mat = Arc::new(Dielectric::new(1.5));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.push(Box::new(sphere));
+            }
+        }
+    }
+
+    let mat1 = Arc::new(Dielectric::new(1.5));
+    let mat2 = Arc::new(Lambert::new(Color::new(0.4, 0.2, 0.1)));
+    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+
+    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
+    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
+    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
+
+    world.push(Box::new(sphere1));
+    world.push(Box::new(sphere2));
+    world.push(Box::new(sphere3));
+
+    let camera = CameraSettings {
+        origin: Point3::new(13.0, 2.0, 3.0),
+        at: Point3::new(0.0, 0.0, 0.0),
+        vup: Vector3::new(0.0, 1.0, 0.0),
+        fov: 20.0,
+        aperture: 0.1,
+        focus: 10.0,
+        shutter: (0.0, 0.0),
+    };
+
+    Scene {
+        camera,
+        image: ImageSettings::default(),
+        background: Background::default(),
+        world,
+        lights: Lights::new(),
+        animation: None,
+    }
+}
+
+/// Cornell 盒子：左边绿色、右边红色的墙，里面放着两个白色的长方体，只有天花板上的灯照明
+/// smoke 为 true 的时候两个盒子换成黑色和白色的烟雾
+fn cornell(smoke: bool) -> Scene {
+    let red = Arc::new(Lambert::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambert::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambert::new(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    let mut world = World::new();
+    let (x, y, z) = (
+        Vector3::new(555.0, 0.0, 0.0),
+        Vector3::new(0.0, 555.0, 0.0),
+        Vector3::new(0.0, 0.0, 555.0),
+    );
+    let origin = Point3::default();
+    // 墙的法线朝向盒子里面
+    world.push(Box::new(Quad::new(x, z, y, green)));
+    world.push(Box::new(Quad::new(origin, y, z, red)));
+    world.push(Box::new(Quad::new(origin, z, x, white.clone())));
+    world.push(Box::new(Quad::new(y, x, z, white.clone())));
+    world.push(Box::new(Quad::new(z, y, x, white.clone())));
+    // 天花板上朝下的灯
+    let corner = Point3::new(343.0, 554.0, 332.0);
+    let (u, v) = (
+        Vector3::new(-130.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -105.0),
+    );
+    let light: Arc<dyn Hit> = Arc::new(Quad::new(corner, u, v, light));
+    world.push(Box::new(Arc::clone(&light)));
+
+    let tall = Cuboid::new(
+        Point3::new(265.0, 0.0, 295.0),
+        Point3::new(430.0, 330.0, 460.0),
+        white.clone(),
+    );
+    let short = Cuboid::new(
+        Point3::new(130.0, 0.0, 65.0),
+        Point3::new(295.0, 165.0, 230.0),
+        white,
+    );
+    match smoke {
+        true => {
+            let black = Arc::new(Isotropic::new(Color::new(0.0, 0.0, 0.0)));
+            let white = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+            world.push(Box::new(ConstantMedium::new(Arc::new(tall), 0.01, black)));
+            world.push(Box::new(ConstantMedium::new(Arc::new(short), 0.01, white)));
+        }
+        false => {
+            world.push(Box::new(tall));
+            world.push(Box::new(short));
+        }
+    }
+
+    let camera = CameraSettings {
+        origin: Point3::new(278.0, 278.0, -800.0),
+        at: Point3::new(278.0, 278.0, 0.0),
+        vup: Vector3::new(0.0, 1.0, 0.0),
+        fov: 40.0,
+        aperture: 0.0,
+        focus: 10.0,
+        shutter: (0.0, 0.0),
+    };
+    let image = ImageSettings {
+        width: 600,
+        ratio: 1.0,
+        samples: 200,
+        max_depth: 50,
+        display: Display::default(),
+    };
+
+    Scene {
+        camera,
+        image,
+        background: Background::black(),
+        world,
+        lights: vec![light],
+        animation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\norigin = [0, 0, 5]\nat = [0, 0, 0]\nfov = 40\n";
+
+    // 解析失败时的行号和消息
+    fn error(text: &str) -> (usize, String) {
+        match parse(&format!("{CAMERA}{text}"), Path::new("scenes")) {
+            Ok(_) => panic!("expected an error:\n{text}"),
+            Err(error) => (error.line, error.message),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_media() {
+        let medium = |density| {
+            format!(
+                "[materials.fog]\ntype = \"Isotropic\"\nalbedo = [1, 1, 1]\n\n[[objects]]\n\
+                 type = \"ConstantMedium\"\nboundary = \"Sphere\"\ncenter = [0, 0, 0]\n\
+                 radius = 1\nmaterial = \"fog\"\ndensity = {density}\n"
+            )
+        };
+        assert!(parse(&format!("{CAMERA}{}", medium("0.5")), Path::new("")).is_ok());
+        for density in ["0", "-1", "0.0"] {
+            let (line, message) = error(&medium(density));
+            assert_eq!(line, 15, "{message}");
+            assert!(message.contains("positive"), "{message}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_environments() {
+        let environment = "[background]\ntype = \"Environment\"\npath = \"sky.hdr\"\n";
+        assert!(parse(&format!("{CAMERA}{environment}"), Path::new("scenes")).is_ok());
+        for intensity in ["-1", "-0.5"] {
+            let (line, message) = error(&format!("{environment}intensity = {intensity}\n"));
+            assert_eq!(line, 8, "{message}");
+            assert!(message.contains("non-negative"), "{message}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_exposures() {
+        assert!(parse(
+            &format!("{CAMERA}[image]\nexposure = -2.5\n"),
+            Path::new("scenes")
+        )
+        .is_ok());
+        for exposure in ["2000", "-2000"] {
+            let (line, message) = error(&format!("[image]\nexposure = {exposure}\n"));
+            assert_eq!(line, 6, "{message}");
+            assert!(message.contains("exposure"), "{message}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_checker_scales() {
+        let checker = "[textures.checker]\ntype = \"Checker\"\neven = [1, 1, 1]\nodd = [0, 0, 0]\n";
+        assert!(parse(
+            &format!("{CAMERA}{checker}scale = -0.5\n"),
+            Path::new("scenes")
+        )
+        .is_ok());
+        for scale in ["0", "-0.0", "1e-320"] {
+            let (line, message) = error(&format!("{checker}scale = {scale}\n"));
+            assert_eq!(line, 9, "{message}");
+            assert!(message.contains("non-zero"), "{message}");
+        }
+    }
+}
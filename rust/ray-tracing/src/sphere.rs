@@ -1,17 +1,23 @@
-use std::rc::Rc;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
+use crate::aabb::Aabb;
 use crate::hittable::{Hit, HitRecord};
 use crate::material::Material;
-use crate::{ray::Ray, vector3::Point3};
+use crate::sampler::Sampler;
+use crate::{
+    ray::Ray,
+    vector3::{Point3, Vector3},
+};
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, material: Rc<dyn Material>) -> Sphere {
+    pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>) -> Sphere {
         Sphere {
             center,
             radius,
@@ -20,10 +26,18 @@ impl Sphere {
     }
 }
 
-impl Hit for Sphere {
-    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+impl Sphere {
+    // 单位球面上的点对应的纹理坐标，u 绕着 y 轴从 -x 方向开始，v 从下到上
+    fn uv(point: Point3) -> (f64, f64) {
+        let theta = (-point.y).acos();
+        let phi = (-point.z).atan2(point.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // 射线和球心在 center 的球求交，移动的球使用射线发出时刻的球心
+    fn hit_at(&self, center: Point3, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
         // 球心到射线起点的向量，
-        let oc = ray.origin() - self.center;
+        let oc = ray.origin() - center;
 
         let a = ray.direction().dot(ray.direction);
         let b = oc.dot(ray.direction());
@@ -44,7 +58,8 @@ impl Hit for Sphere {
         }
 
         let point = ray.at(root);
-        let mut normal = (point - self.center) / self.radius;
+        let mut normal = (point - center) / self.radius;
+        let (u, v) = Self::uv(normal);
 
         let face = ray.direction.dot(normal) < 0.0;
         if !face {
@@ -55,8 +70,142 @@ impl Hit for Sphere {
             point,
             normal,
             t: root,
+            u,
+            v,
             face,
-            material: Rc::clone(&self.material),
+            object: 0,
+            material: Arc::clone(&self.material),
         })
     }
 }
+
+impl Hit for Sphere {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        self.hit_at(self.center, ray, min, max)
+    }
+
+    // 半径是负数的球（空心玻璃球的内表面）包围盒也一样大
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        let radius = Vector3::new(radius, radius, radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    // 在 origin 看到球的圆锥里均匀采样
+    fn random_direction(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.dot(direction);
+        if distance_squared <= self.radius * self.radius {
+            return direction;
+        }
+
+        let cos_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let cos = 1.0 + sampler.gen() * (cos_max - 1.0);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.gen();
+
+        let w = direction.unit();
+        let (u, v) = w.basis();
+        sin * phi.cos() * u + sin * phi.sin() * v + cos * w
+    }
+
+    fn pdf(&self, origin: Point3, direction: Vector3) -> f64 {
+        let offset = self.center - origin;
+        let distance_squared = offset.dot(offset);
+        // 起点在球里面的时候没办法按圆锥采样
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+        if self
+            .hit(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let cos_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+}
+
+// 匀速运动的球，time0 时刻球心在 center0，time1 时刻在 center1，
+// 这段时间之前和之后停在两端，快门时间更长的时候也不会离开包围盒
+pub struct MovingSphere {
+    sphere: Sphere,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> MovingSphere {
+        MovingSphere {
+            sphere: Sphere::new(center0, radius, material),
+            center1,
+            time0,
+            time1,
+        }
+    }
+
+    fn center(&self, time: f64) -> Point3 {
+        let center0 = self.sphere.center;
+        // 两个时刻相同的时候球是静止的
+        if self.time1 == self.time0 {
+            return center0;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        center0 + t * (self.center1 - center0)
+    }
+}
+
+impl Hit for MovingSphere {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        self.sphere.hit_at(self.center(ray.time), ray, min, max)
+    }
+
+    // 包围盒要包含整个运动过程
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = self.sphere.radius.abs();
+        let radius = Vector3::new(radius, radius, radius);
+        let start = Aabb::new(self.sphere.center - radius, self.sphere.center + radius);
+        let end = Aabb::new(self.center1 - radius, self.center1 + radius);
+        Some(start.union(end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambert;
+    use crate::vector3::Color;
+
+    #[test]
+    fn moving_sphere_stays_in_bounding_box() {
+        let material = Arc::new(Lambert::new(Color::new(0.5, 0.5, 0.5)));
+        let (center0, center1) = (Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0));
+        let sphere = MovingSphere::new(center0, center1, 0.0, 1.0, 0.5, material);
+        let bbox = sphere.bounding_box().unwrap();
+
+        // 快门时间在 [time0, time1] 之外的时候球停在两端
+        for (time, x) in [(-1.0, 0.0), (0.5, 1.0), (2.0, 2.0), (5.0, 2.0)] {
+            let center = sphere.center(time);
+            assert_eq!((center.x, center.y, center.z), (x, 0.0, 0.0));
+            let ray = Ray::new(Point3::new(x, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0), time);
+            let record = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!(bbox.hit(&ray, 0.001, record.t));
+        }
+        let ray = Ray::new(
+            Point3::new(4.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            2.0,
+        );
+        assert!(sphere.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}
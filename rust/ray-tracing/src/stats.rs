@@ -0,0 +1,303 @@
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::ops::AddAssign;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 终端上原地刷新进度的间隔，输出重定向到文件的时候每隔更长的时间输出一行
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(100);
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+thread_local! {
+    // 求交测试不方便通过 Hit 的参数传递，每个线程单独计数，渲染完一块之后取出来
+    static TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// 记录 count 次求交测试（包围盒或者物体）
+pub fn count_tests(count: u64) {
+    TESTS.with(|tests| tests.set(tests.get() + count));
+}
+
+/// 取出当前线程记录的求交测试次数并清零
+pub fn take_tests() -> u64 {
+    TESTS.with(|tests| tests.replace(0))
+}
+
+// 渲染过程中的计数
+#[derive(Clone, Copy, Default)]
+pub struct Counters {
+    pub samples: u64,
+    // 从相机发出的射线
+    pub primary_rays: u64,
+    // 散射之后继续追踪的射线
+    pub bounce_rays: u64,
+    // 对光源采样时的阴影射线
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+}
+
+impl Counters {
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.bounce_rays + self.shadow_rays
+    }
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, rhs: Self) {
+        self.samples += rhs.samples;
+        self.primary_rays += rhs.primary_rays;
+        self.bounce_rays += rhs.bounce_rays;
+        self.shadow_rays += rhs.shadow_rays;
+        self.intersection_tests += rhs.intersection_tests;
+    }
+}
+
+// 一次渲染的统计信息
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    pub width: u64,
+    pub height: u64,
+    pub threads: usize,
+    pub counters: Counters,
+    pub elapsed: Duration,
+    pub tiles: u64,
+    // 所有块的渲染时间之和，以及最慢的一块
+    pub tile_time: Duration,
+    pub slowest_tile: Duration,
+}
+
+impl Stats {
+    /// 平均每条路径的射线段数（不算阴影射线）
+    pub fn path_length(&self) -> f64 {
+        let c = &self.counters;
+        ratio((c.primary_rays + c.bounce_rays) as f64, c.samples as f64)
+    }
+
+    pub fn average_tile(&self) -> Duration {
+        self.tile_time
+            .checked_div(self.tiles as u32)
+            .unwrap_or_default()
+    }
+
+    /// 渲染结束时输出的统计摘要
+    pub fn summary(&self) -> String {
+        let c = &self.counters;
+        let seconds = self.elapsed.as_secs_f64();
+        let mut text = String::new();
+        let _ = writeln!(text, "Render time: {:.2} s", seconds);
+        let _ = writeln!(
+            text,
+            "Samples: {} ({}/s)",
+            c.samples,
+            rate(ratio(c.samples as f64, seconds))
+        );
+        let _ = writeln!(
+            text,
+            "Rays: {} ({}/s), primary {}, secondary {} (bounce {}, shadow {})",
+            c.rays(),
+            rate(ratio(c.rays() as f64, seconds)),
+            c.primary_rays,
+            c.bounce_rays + c.shadow_rays,
+            c.bounce_rays,
+            c.shadow_rays
+        );
+        let _ = writeln!(
+            text,
+            "Intersection tests: {} ({:.1} per ray)",
+            c.intersection_tests,
+            ratio(c.intersection_tests as f64, c.rays() as f64)
+        );
+        let _ = writeln!(text, "Average path length: {:.2}", self.path_length());
+        let _ = write!(
+            text,
+            "Tiles: {}, {:.1} ms on average, slowest {:.1} ms",
+            self.tiles,
+            self.average_tile().as_secs_f64() * 1e3,
+            self.slowest_tile.as_secs_f64() * 1e3
+        );
+        text
+    }
+
+    /// 保存给其他工具读取的 JSON
+    pub fn json(&self) -> String {
+        let c = &self.counters;
+        let seconds = self.elapsed.as_secs_f64();
+        format!(
+            r#"{{
+  "width": {},
+  "height": {},
+  "threads": {},
+  "elapsed_seconds": {},
+  "samples": {},
+  "samples_per_second": {},
+  "rays": {{
+    "total": {},
+    "primary": {},
+    "secondary": {},
+    "bounce": {},
+    "shadow": {},
+    "per_second": {}
+  }},
+  "intersection_tests": {},
+  "average_path_length": {},
+  "tiles": {{
+    "count": {},
+    "average_seconds": {},
+    "slowest_seconds": {}
+  }}
+}}
+"#,
+            self.width,
+            self.height,
+            self.threads,
+            seconds,
+            c.samples,
+            ratio(c.samples as f64, seconds),
+            c.rays(),
+            c.primary_rays,
+            c.bounce_rays + c.shadow_rays,
+            c.bounce_rays,
+            c.shadow_rays,
+            ratio(c.rays() as f64, seconds),
+            c.intersection_tests,
+            self.path_length(),
+            self.tiles,
+            self.average_tile().as_secs_f64(),
+            self.slowest_tile.as_secs_f64()
+        )
+    }
+}
+
+// 分母是 0 的时候是 0，JSON 里不能有 NaN
+fn ratio(a: f64, b: f64) -> f64 {
+    match b > 0.0 {
+        true => a / b,
+        false => 0.0,
+    }
+}
+
+// 进度输出，终端上在同一行原地刷新
+pub struct Progress {
+    enabled: bool,
+    terminal: bool,
+    start: Instant,
+    // 这次渲染总共要完成的采样数，自适应采样时是最多的采样数
+    total: u64,
+    state: Mutex<State>,
+}
+
+struct State {
+    counters: Counters,
+    printed: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(enabled: bool, total: u64) -> Progress {
+        Progress {
+            enabled,
+            terminal: io::stderr().is_terminal(),
+            start: Instant::now(),
+            total,
+            state: Mutex::new(State {
+                counters: Counters::default(),
+                printed: None,
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 累加一块的计数，距离上次输出足够久的时候刷新进度
+    pub fn add(&self, counters: Counters) {
+        let mut state = self.state.lock().unwrap();
+        state.counters += counters;
+        if !self.enabled {
+            return;
+        }
+        let interval = match self.terminal {
+            true => TERMINAL_INTERVAL,
+            false => LOG_INTERVAL,
+        };
+        if state
+            .printed
+            .is_some_and(|printed| printed.elapsed() < interval)
+        {
+            return;
+        }
+        state.printed = Some(Instant::now());
+        self.print(&state.counters, false);
+    }
+
+    /// 输出一行其他的信息，不和进度混在同一行
+    pub fn message(&self, text: &str) {
+        let _state = self.state.lock().unwrap();
+        match self.terminal {
+            true => eprintln!("\r\x1b[K{text}"),
+            false => eprintln!("{text}"),
+        }
+    }
+
+    /// 输出最终的进度并换行，返回累加的计数。自适应采样提前结束的时候也是 100%
+    pub fn finish(&self) -> Counters {
+        let state = self.state.lock().unwrap();
+        if self.enabled {
+            self.print(&state.counters, true);
+            if self.terminal {
+                eprintln!();
+            }
+        }
+        state.counters
+    }
+
+    fn print(&self, counters: &Counters, finished: bool) {
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs_f64();
+        let fraction = match (finished, self.total) {
+            (true, _) | (_, 0) => 1.0,
+            (false, total) => (counters.samples as f64 / total as f64).min(1.0),
+        };
+        // 按照目前的速度估计剩下的时间
+        let eta = match fraction > 0.0 {
+            true => duration(elapsed.mul_f64((1.0 - fraction) / fraction)),
+            false => "--".to_string(),
+        };
+        let line = format!(
+            "{:5.1}% elapsed {} ETA {} | {} samples/s | {} rays/s",
+            100.0 * fraction,
+            duration(elapsed),
+            eta,
+            rate(ratio(counters.samples as f64, seconds)),
+            rate(ratio(counters.rays() as f64, seconds))
+        );
+        let mut stderr = io::stderr().lock();
+        let _ = match self.terminal {
+            true => write!(stderr, "\r\x1b[K{line}"),
+            false => writeln!(stderr, "{line}"),
+        };
+        let _ = stderr.flush();
+    }
+}
+
+// 时:分:秒
+fn duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// 带单位的速度，比如 1.25M
+fn rate(value: f64) -> String {
+    match value {
+        v if v >= 1e9 => format!("{:.2}G", v / 1e9),
+        v if v >= 1e6 => format!("{:.2}M", v / 1e6),
+        v if v >= 1e3 => format!("{:.2}K", v / 1e3),
+        v => format!("{v:.0}"),
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use crate::color::linear;
+use crate::image::Image;
+use crate::vector3::{Color, Point3};
+
+// 纹理，根据表面的纹理坐标和交点的位置得到颜色
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+}
+
+// 纯色
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Point3) -> Color {
+        self.color
+    }
+}
+
+// 三维空间里的棋盘格，和物体的形状无关
+pub struct Checker {
+    // 每个格子的边长的倒数
+    inverse_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Checker {
+        Checker {
+            inverse_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let x = (self.inverse_scale * point.x).floor() as i64;
+        let y = (self.inverse_scale * point.y).floor() as i64;
+        let z = (self.inverse_scale * point.z).floor() as i64;
+
+        match (x + y + z) % 2 == 0 {
+            true => self.even.value(u, v, point),
+            false => self.odd.value(u, v, point),
+        }
+    }
+}
+
+// 图片纹理，u 从左到右，v 从下到上
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // 转换成线性空间的颜色
+    colors: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(image: &Image) -> ImageTexture {
+        // 图片是 sRGB 编码的
+        let colors = image
+            .data
+            .chunks(3)
+            .map(|rgb| Color::new(linear(rgb[0]), linear(rgb[1]), linear(rgb[2])))
+            .collect();
+
+        ImageTexture {
+            width: image.width as usize,
+            height: image.height as usize,
+            colors,
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Point3) -> Color {
+        if self.colors.is_empty() {
+            // 没有数据的图片显示成青色，方便发现问题
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // 图片的第一行在最上面，所以 v 需要反过来
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.colors[j * self.width + i]
+    }
+}
@@ -0,0 +1,655 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+// 场景文件使用的 TOML 子集：注释、[表]、[[表数组]]、键值对，
+// 值支持字符串、整数、浮点数、布尔值、数组（可以跨多行）和内联表。
+// 每个值都记录了所在的行号，方便报告错误。
+
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(line: usize, message: impl Into<String>) -> Error {
+        let message = message.into();
+        Error { line, message }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 行号为 0 表示和具体的行无关，比如文件读取失败
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {line}: {}", self.message),
+        }
+    }
+}
+
+pub enum Kind {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub struct Value {
+    pub kind: Kind,
+    pub line: usize,
+}
+
+#[derive(Default)]
+pub struct Table {
+    // 表头所在的行，根表是 0
+    pub line: usize,
+    // 表的名字，用来报告错误
+    pub name: String,
+    // 是否已经有了表头（或者是内联表），只是因为子表被隐式创建的表可以再补上表头
+    defined: bool,
+    entries: BTreeMap<String, Value>,
+}
+
+impl Table {
+    fn new(line: usize, name: String) -> Table {
+        Table {
+            line,
+            name,
+            defined: true,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    pub fn required(&self, key: &str) -> Result<&Value, Error> {
+        self.get(key).ok_or_else(|| match self.name.is_empty() {
+            true => Error::new(self.line, format!("missing key `{key}`")),
+            false => Error::new(self.line, format!("missing key `{key}` in [{}]", self.name)),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter()
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), Error> {
+        let line = value.line;
+        if self.entries.insert(key.clone(), value).is_some() {
+            return Err(Error::new(line, format!("duplicate key `{key}`")));
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self.kind {
+            Kind::String(_) => "string",
+            Kind::Integer(_) => "integer",
+            Kind::Float(_) => "float",
+            Kind::Boolean(_) => "boolean",
+            Kind::Array(_) => "array",
+            Kind::Table(_) => "table",
+        }
+    }
+
+    fn expected<T>(&self, name: &str) -> Result<T, Error> {
+        let message = format!("expected {name}, found {}", self.type_name());
+        Err(Error::new(self.line, message))
+    }
+
+    pub fn as_str(&self) -> Result<&str, Error> {
+        match &self.kind {
+            Kind::String(value) => Ok(value),
+            _ => self.expected("string"),
+        }
+    }
+
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        match self.kind {
+            Kind::Integer(value) => Ok(value as f64),
+            Kind::Float(value) => Ok(value),
+            _ => self.expected("number"),
+        }
+    }
+
+    pub fn as_u64(&self) -> Result<u64, Error> {
+        match self.kind {
+            Kind::Integer(value) if value >= 0 => Ok(value as u64),
+            Kind::Integer(_) => Err(Error::new(self.line, "expected a non-negative integer")),
+            _ => self.expected("integer"),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match self.kind {
+            Kind::Boolean(value) => Ok(value),
+            _ => self.expected("boolean"),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], Error> {
+        match &self.kind {
+            Kind::Array(values) => Ok(values),
+            _ => self.expected("array"),
+        }
+    }
+
+    pub fn as_table(&self) -> Result<&Table, Error> {
+        match &self.kind {
+            Kind::Table(table) => Ok(table),
+            _ => self.expected("table"),
+        }
+    }
+}
+
+/// 解析整个文件，返回根表
+pub fn parse(text: &str) -> Result<Table, Error> {
+    let mut root = Table::default();
+    // 当前所在的表的路径，表数组取最后一个元素
+    let mut path: Vec<String> = Vec::new();
+    let mut parser = Parser::new(text);
+
+    loop {
+        parser.skip_whitespace();
+        let number = parser.line;
+        match parser.peek() {
+            None => break,
+            Some('\n' | '#') => {}
+            Some('[') => {
+                parser.next();
+                let array = parser.eat('[');
+                let keys = parser.parse_path()?;
+                parser.expect(']')?;
+                if array {
+                    parser.expect(']')?;
+                }
+
+                let name = keys.join(".");
+                let (last, parents) = keys.split_last().unwrap();
+                let parent = table_at(&mut root, parents, number)?;
+                match array {
+                    true => push_array_table(parent, last, number, name)?,
+                    false => define_table(parent, last, number, name)?,
+                }
+                path = keys;
+            }
+            Some(_) => {
+                let keys = parser.parse_path()?;
+                parser.expect('=')?;
+                let value = parser.parse_value()?;
+
+                let (last, parents) = keys.split_last().unwrap();
+                let table = table_at(&mut root, &path, number)?;
+                let table = table_at(table, parents, number)?;
+                table.insert(last.clone(), value)?;
+            }
+        }
+        parser.finish()?;
+    }
+
+    Ok(root)
+}
+
+// 按照路径找到子表，不存在的时候自动创建，表数组取最后一个元素
+fn table_at<'a>(
+    table: &'a mut Table,
+    keys: &[String],
+    line: usize,
+) -> Result<&'a mut Table, Error> {
+    let Some((first, rest)) = keys.split_first() else {
+        return Ok(table);
+    };
+
+    let name = match table.name.is_empty() {
+        true => first.clone(),
+        false => format!("{}.{first}", table.name),
+    };
+    let value = table.entries.entry(first.clone()).or_insert_with(|| Value {
+        kind: Kind::Table(Table {
+            defined: false,
+            ..Table::new(line, name)
+        }),
+        line,
+    });
+
+    let child = match &mut value.kind {
+        Kind::Table(child) => child,
+        Kind::Array(values) => match values.last_mut().map(|value| &mut value.kind) {
+            Some(Kind::Table(child)) => child,
+            _ => return Err(Error::new(line, format!("`{first}` is not a table"))),
+        },
+        _ => return Err(Error::new(line, format!("`{first}` is not a table"))),
+    };
+    table_at(child, rest, line)
+}
+
+fn define_table(parent: &mut Table, key: &str, line: usize, name: String) -> Result<(), Error> {
+    match parent.entries.get_mut(key) {
+        // 之前因为子表被隐式创建过，现在补上行号
+        Some(Value {
+            kind: Kind::Table(table),
+            ..
+        }) if !table.defined => {
+            table.line = line;
+            table.defined = true;
+            Ok(())
+        }
+        Some(_) => Err(Error::new(line, format!("table [{name}] defined twice"))),
+        None => {
+            let kind = Kind::Table(Table::new(line, name));
+            parent.insert(key.to_string(), Value { kind, line })
+        }
+    }
+}
+
+fn push_array_table(parent: &mut Table, key: &str, line: usize, name: String) -> Result<(), Error> {
+    let kind = Kind::Table(Table::new(line, name));
+    let table = Value { kind, line };
+    match parent.entries.get_mut(key) {
+        Some(Value {
+            kind: Kind::Array(values),
+            ..
+        }) => values.push(table),
+        Some(_) => {
+            return Err(Error::new(
+                line,
+                format!("`{key}` is not an array of tables"),
+            ))
+        }
+        None => {
+            let kind = Kind::Array(vec![table]);
+            parent.insert(key.to_string(), Value { kind, line })?;
+        }
+    }
+    Ok(())
+}
+
+// 逐个字符解析整个文件，记录当前所在的行
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Parser<'a> {
+        let chars = text.char_indices().peekable();
+        Parser {
+            text,
+            chars,
+            line: 1,
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error::new(self.line, message))
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next().map(|(_, c)| c);
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    // 同一行里的空白
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\r')) {
+            self.next();
+        }
+    }
+
+    // 数组里的空白、换行和注释
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('\n') => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.next();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some('\n') | None => self.error(format!("expected `{expected}`, found end of line")),
+            Some(c) => self.error(format!("expected `{expected}`, found `{c}`")),
+        }
+    }
+
+    // 一行的剩余部分只能是空白或者注释
+    fn finish(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next();
+                Ok(())
+            }
+            Some('#') => {
+                self.skip_comment();
+                self.next();
+                Ok(())
+            }
+            Some(c) => self.error(format!("unexpected `{c}`")),
+        }
+    }
+
+    // 以点分隔的键，比如 `materials.ground`
+    fn parse_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys = vec![self.parse_key()?];
+        loop {
+            self.skip_whitespace();
+            if !self.eat('.') {
+                return Ok(keys);
+            }
+            keys.push(self.parse_key()?);
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, Error> {
+        self.skip_whitespace();
+        if self.eat('"') {
+            return self.parse_string();
+        }
+
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            key.push(c);
+            self.next();
+        }
+        match key.is_empty() {
+            true => self.error("expected a key"),
+            false => Ok(key),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace();
+        let line = self.line;
+        let kind = match self.peek() {
+            Some('"') => {
+                self.next();
+                Kind::String(self.parse_string()?)
+            }
+            Some('[') => {
+                self.next();
+                Kind::Array(self.parse_array()?)
+            }
+            Some('{') => {
+                self.next();
+                Kind::Table(self.parse_inline_table()?)
+            }
+            Some(_) => self.parse_scalar()?,
+            None => return self.error("expected a value"),
+        };
+        Ok(Value { kind, line })
+    }
+
+    // 字符串不能跨行
+    fn parse_string(&mut self) -> Result<String, Error> {
+        let mut result = String::new();
+        loop {
+            match self.string_char()? {
+                '"' => return Ok(result),
+                '\\' => match self.string_char()? {
+                    'n' => result.push('\n'),
+                    't' => result.push('\t'),
+                    '"' => result.push('"'),
+                    '\\' => result.push('\\'),
+                    c => return self.error(format!("unknown escape `\\{c}`")),
+                },
+                c => result.push(c),
+            }
+        }
+    }
+
+    // 字符串里的下一个字符，到了行尾还没有结束就是错误
+    fn string_char(&mut self) -> Result<char, Error> {
+        match self.peek() {
+            None | Some('\n') => self.error("unterminated string"),
+            Some(c) => {
+                self.next();
+                Ok(c)
+            }
+        }
+    }
+
+    // 数组可以跨多行，最后一个元素后面可以有逗号
+    fn parse_array(&mut self) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.eat(']') {
+                return Ok(values);
+            }
+            values.push(self.parse_value()?);
+            self.skip_blank();
+            if !self.eat(',') {
+                self.expect(']')?;
+                return Ok(values);
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Table, Error> {
+        let mut table = Table::new(self.line, String::new());
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(table);
+        }
+        loop {
+            let key = self.parse_key()?;
+            self.expect('=')?;
+            let value = self.parse_value()?;
+            table.insert(key, value)?;
+            self.skip_whitespace();
+            if !self.eat(',') {
+                self.expect('}')?;
+                return Ok(table);
+            }
+        }
+    }
+
+    // 数字或者布尔值
+    fn parse_scalar(&mut self) -> Result<Kind, Error> {
+        let start = self.chars.peek().map_or(self.text.len(), |&(i, _)| i);
+        while let Some(c) = self.peek() {
+            if matches!(c, ',' | ']' | '}' | '#') || c.is_whitespace() {
+                break;
+            }
+            self.next();
+        }
+        let end = self.chars.peek().map_or(self.text.len(), |&(i, _)| i);
+        let token = &self.text[start..end];
+        match token {
+            "true" => return Ok(Kind::Boolean(true)),
+            "false" => return Ok(Kind::Boolean(false)),
+            _ => {}
+        }
+
+        let number = token.replace('_', "");
+        if let Ok(value) = number.parse::<i64>() {
+            return Ok(Kind::Integer(value));
+        }
+        match number.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Kind::Float(value)),
+            _ => self.error(format!("invalid value `{token}`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 解析失败时返回错误所在的行
+    fn error_line(text: &str) -> usize {
+        match parse(text) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.line,
+        }
+    }
+
+    #[test]
+    fn parses_values() {
+        let text = r#"
+# 注释
+name = "a \"quoted\" \\ path\tx\n"  # 行尾的注释
+count = 1_000
+negative = -3
+scale = 2.5e-1
+on = true
+off = false
+point = [1, 2.5, [3]]
+empty = []
+inline = { a = 1, "b c" = "d" }
+"#;
+        let root = parse(text).unwrap();
+        assert_eq!(
+            root.required("name").unwrap().as_str().unwrap(),
+            "a \"quoted\" \\ path\tx\n"
+        );
+        assert_eq!(root.required("count").unwrap().as_u64().unwrap(), 1000);
+        assert_eq!(root.required("negative").unwrap().as_f64().unwrap(), -3.0);
+        assert!(root.required("negative").unwrap().as_u64().is_err());
+        assert_eq!(root.required("scale").unwrap().as_f64().unwrap(), 0.25);
+        assert!(root.required("on").unwrap().as_bool().unwrap());
+        assert!(!root.required("off").unwrap().as_bool().unwrap());
+        assert!(root.required("on").unwrap().as_f64().is_err());
+
+        let point = root.required("point").unwrap().as_array().unwrap();
+        assert_eq!(point.len(), 3);
+        assert_eq!(point[1].as_f64().unwrap(), 2.5);
+        assert_eq!(point[2].as_array().unwrap()[0].as_u64().unwrap(), 3);
+        assert!(root
+            .required("empty")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .is_empty());
+
+        let inline = root.required("inline").unwrap().as_table().unwrap();
+        assert_eq!(inline.required("b c").unwrap().as_str().unwrap(), "d");
+        assert_eq!(root.required("inline").unwrap().line, 11);
+    }
+
+    #[test]
+    fn arrays_span_lines() {
+        let text = "points = [\n  [0, 0, 0],  # 原点\n\n  [1, 2, 3],\n]\nafter = 1\n";
+        let root = parse(text).unwrap();
+        let points = root.required("points").unwrap().as_array().unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].line, 4);
+        assert_eq!(points[1].as_array().unwrap()[2].as_f64().unwrap(), 3.0);
+        assert_eq!(root.required("after").unwrap().line, 6);
+    }
+
+    #[test]
+    fn parses_tables() {
+        let text = r#"
+[materials.ground]
+type = "Lambert"
+
+[materials.glass]
+type = "Dielectric"
+
+[[objects]]
+type = "Sphere"
+
+[[objects]]
+type = "Quad"
+size.x = 2
+
+[[animation.keyframes]]
+frame = 1
+"#;
+        let root = parse(text).unwrap();
+        let materials = root.required("materials").unwrap().as_table().unwrap();
+        let glass = materials.required("glass").unwrap().as_table().unwrap();
+        assert_eq!(glass.line, 5);
+        assert_eq!(glass.name, "materials.glass");
+
+        let objects = root.required("objects").unwrap().as_array().unwrap();
+        assert_eq!(objects.len(), 2);
+        let quad = objects[1].as_table().unwrap();
+        assert_eq!(quad.required("type").unwrap().as_str().unwrap(), "Quad");
+        let size = quad.required("size").unwrap().as_table().unwrap();
+        assert_eq!(size.required("x").unwrap().as_u64().unwrap(), 2);
+
+        let animation = root.required("animation").unwrap().as_table().unwrap();
+        let keyframes = animation.required("keyframes").unwrap().as_array().unwrap();
+        assert_eq!(keyframes.len(), 1);
+
+        // 缺少的键报告表头所在的行
+        let error = glass.required("refraction").err().unwrap();
+        assert_eq!(error.line, 5);
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        assert_eq!(error_line("a = 1\nb = 2\na = 3\n"), 3);
+        assert_eq!(error_line("x = { a = 1, a = 2 }\n"), 1);
+        assert_eq!(error_line("[a]\n[b]\n[a]\n"), 3);
+        // 第一次出现的时候没有键，也不能重复
+        assert_eq!(error_line("[a]\n\n[a]\nx = 1\n"), 3);
+        assert_eq!(error_line("[a.b]\nx = 1\n[a]\n[a]\n"), 4);
+        assert_eq!(error_line("a = 1\n[a]\n"), 2);
+        assert_eq!(error_line("[[a]]\n[a]\n"), 2);
+        assert_eq!(error_line("a = 1\n[[a]]\n"), 2);
+        // 隐式创建的表可以补上表头
+        assert!(parse("[a.b]\nx = 1\n[a]\ny = 2\n").is_ok());
+    }
+
+    #[test]
+    fn reports_error_lines() {
+        assert_eq!(error_line("a = 1\nb = \"open\nc = 2\n"), 2);
+        assert_eq!(error_line("a = \"\\q\"\n"), 1);
+        assert_eq!(error_line("\n\na = 1 2\n"), 3);
+        assert_eq!(error_line("a = [1,\n2\n3]\n"), 3);
+        assert_eq!(error_line("a = [1,\n2,\n"), 3);
+        assert_eq!(error_line("a = \nb = 1\n"), 1);
+        assert_eq!(error_line("[a\n"), 1);
+        assert_eq!(error_line("a = tru\n"), 1);
+        assert_eq!(error_line("a = { b = 1,\nc = 2 }\n"), 1);
+    }
+}
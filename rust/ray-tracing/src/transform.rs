@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord};
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::vector3::Point3;
+
+// 物体的实例，同一个物体可以用不同的变换放在场景里的多个地方。
+// 射线先变换到物体自己的坐标系里求交，交点和法线再变换回世界坐标系。
+pub struct Transform {
+    object: Arc<dyn Hit>,
+    // 物体坐标到世界坐标
+    matrix: Matrix4,
+    // 世界坐标到物体坐标
+    inverse: Matrix4,
+    // 法线使用逆矩阵的转置变换，非均匀缩放之后才能和表面保持垂直
+    normal_matrix: Matrix4,
+    bbox: Option<Aabb>,
+}
+
+impl Transform {
+    /// 矩阵不可逆的时候返回 None
+    pub fn new(object: Arc<dyn Hit>, matrix: Matrix4) -> Option<Transform> {
+        let inverse = matrix.inverse()?;
+
+        // 变换包围盒的八个角，再求新的包围盒
+        let bbox = object.bounding_box().map(|bbox| {
+            let corner = |i: usize| {
+                let pick = |bit: usize, axis: usize| match i >> bit & 1 {
+                    0 => bbox.min[axis],
+                    _ => bbox.max[axis],
+                };
+                matrix.point(Point3::new(pick(0, 0), pick(1, 1), pick(2, 2)))
+            };
+            (1..8).fold(Aabb::from_points(corner(0), corner(0)), |result, i| {
+                result.union(Aabb::from_points(corner(i), corner(i)))
+            })
+        });
+
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bbox,
+        })
+    }
+}
+
+impl Hit for Transform {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        // 方向不归一化，两个坐标系里的 t 是一样的
+        let mut local = Ray::new(
+            self.inverse.point(ray.origin),
+            self.inverse.vector(ray.direction),
+            ray.time,
+        );
+        local.seed = ray.seed;
+        let mut record = self.object.hit(&local, min, max)?;
+
+        record.point = self.matrix.point(record.point);
+        record.normal = self.normal_matrix.vector(record.normal).unit();
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambert;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use crate::vector3::{Color, Vector3};
+
+    #[test]
+    fn inverse_undoes_composition() {
+        let matrix = Matrix4::translation(Vector3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation(Vector3::new(1.0, 1.0, 0.0), 37.0)
+            * Matrix4::scaling(Vector3::new(2.0, 0.5, 3.0));
+        let product = matrix * matrix.inverse().unwrap();
+        let identity = Matrix4::identity();
+        let point = Point3::new(0.3, -4.0, 7.0);
+        assert!((product.point(point) - identity.point(point)).length() < 1e-12);
+
+        let singular = Matrix4::scaling(Vector3::new(1.0, 0.0, 1.0));
+        assert!(singular.inverse().is_none());
+    }
+
+    #[test]
+    fn instance_matches_transformed_geometry() {
+        let material = Arc::new(Lambert::new(Color::new(0.5, 0.5, 0.5)));
+        let unit: Arc<dyn Hit> = Arc::new(Sphere::new(Point3::default(), 1.0, material.clone()));
+        // 旋转不会改变球的形状，平移加上均匀缩放应该和直接放一个大球一样
+        let matrix = Matrix4::translation(Vector3::new(3.0, 1.0, -2.0))
+            * Matrix4::rotation(Vector3::new(0.0, 1.0, 0.0), 45.0)
+            * Matrix4::scaling(Vector3::new(2.0, 2.0, 2.0));
+        let instance = Transform::new(unit, matrix).unwrap();
+        let sphere = Sphere::new(Point3::new(3.0, 1.0, -2.0), 2.0, material);
+
+        let mut sampler = Sampler::new(3);
+        for _ in 0..10000 {
+            let origin = Point3::random(-10.0..10.0, &mut sampler);
+            let ray = Ray::new(origin, Vector3::random_unit(&mut sampler), 0.0);
+            let expected = sphere.hit(&ray, 0.001, f64::INFINITY);
+            let actual = instance.hit(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (Some(a), Some(b)) => {
+                    assert!((a.t - b.t).abs() < 1e-9);
+                    assert!((a.point - b.point).length() < 1e-9);
+                    assert!((a.normal - b.normal).length() < 1e-9);
+                    assert_eq!(a.face, b.face);
+                }
+                (None, None) => {}
+                _ => panic!("instance and sphere disagree"),
+            }
+        }
+
+        let bbox = instance.bounding_box().unwrap();
+        let center = Point3::new(3.0, 1.0, -2.0);
+        assert!((bbox.min - center).length() >= 2.0 * 3f64.sqrt() - 1e-9);
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vector3::{Point3, Vector3};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    // 顶点的法线，有的时候对法线插值，得到平滑的表面
+    normals: Option<[Vector3; 3]>,
+    // 顶点的纹理坐标，没有的时候使用重心坐标
+    uvs: Option<[(f64, f64); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vector3; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Triangle {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl Hit for Triangle {
+    // 水密的射线和三角形求交（Woop, Benthin, Wald 2013），
+    // 相邻的三角形共享的边上不会因为浮点误差漏掉交点。
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+        let direction = ray.direction;
+
+        // 方向分量绝对值最大的轴作为 z 轴，保证 z 轴方向为正
+        let abs = Vector3::new(direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let kz = match abs.x > abs.y {
+            true if abs.x > abs.z => 0,
+            false if abs.y > abs.z => 1,
+            _ => 2,
+        };
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        if direction[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // 剪切变换，把射线变成沿着 z 轴的单位射线
+        let sz = 1.0 / direction[kz];
+        let sx = direction[kx] * sz;
+        let sy = direction[ky] * sz;
+
+        let [a, b, c] = self.vertices.map(|vertex| vertex - ray.origin);
+        let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+        let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+        let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+        // 缩放的重心坐标，符号不一致说明射线没有穿过三角形
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+
+        let t = (u * a[kz] + v * b[kz] + w * c[kz]) * sz / det;
+        if t < min || max < t {
+            return None;
+        }
+
+        // 三个顶点的重心坐标
+        let (b0, b1, b2) = (u / det, v / det, w / det);
+        let [p0, p1, p2] = self.vertices;
+        let point = b0 * p0 + b1 * p1 + b2 * p2;
+
+        let geometric = (p1 - p0).cross(p2 - p0).unit();
+        let face = direction.dot(geometric) < 0.0;
+        let mut normal = match self.normals {
+            Some([n0, n1, n2]) => (b0 * n0 + b1 * n1 + b2 * n2).unit(),
+            None => geometric,
+        };
+        // 插值的法线也要和几何法线在同一侧
+        if normal.dot(geometric) < 0.0 {
+            normal = -1.0 * normal;
+        }
+        if !face {
+            normal = -1.0 * normal;
+        }
+
+        let (u, v) = match self.uvs {
+            Some([(u0, v0), (u1, v1), (u2, v2)]) => {
+                (b0 * u0 + b1 * u1 + b2 * u2, b0 * v0 + b1 * v1 + b2 * v2)
+            }
+            None => (b1, b2),
+        };
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u,
+            v,
+            face,
+            object: 0,
+            material: Arc::clone(&self.material),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices;
+        let bbox = Aabb::from_points(p0, p1).union(Aabb::from_points(p2, p2));
+        // 和坐标轴平行的三角形包围盒厚度为 0，稍微扩大一点
+        let padding = Vector3::new(1e-6, 1e-6, 1e-6);
+        Some(Aabb::new(bbox.min - padding, bbox.max + padding))
+    }
+}
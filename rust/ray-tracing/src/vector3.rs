@@ -1,11 +1,11 @@
-use std::ops::{Add, AddAssign, Div, Mul, Range, Sub};
+use std::ops::{Add, AddAssign, Div, Index, Mul, Range, Sub};
 
-use rand::Rng;
+use crate::sampler::Sampler;
 
 pub type Color = Vector3;
 pub type Point3 = Vector3;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct Vector3 {
     pub x: f64,
     pub y: f64,
@@ -13,16 +13,13 @@ pub struct Vector3 {
 }
 
 impl Vector3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
         Self { x, y, z }
     }
 
-    pub fn format_str(&self, samples: f64) -> String {
-        let ir = (256.0 * (self.x / samples).sqrt().clamp(0.0, 0.999)) as u64;
-        let ig = (256.0 * (self.y / samples).sqrt().clamp(0.0, 0.999)) as u64;
-        let ib = (256.0 * (self.z / samples).sqrt().clamp(0.0, 0.999)) as u64;
-
-        format!("{ir} {ig} {ib}\n")
+    /// 颜色的亮度（Rec. 709 的系数）
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
     }
 
     /// 向量的长度
@@ -49,14 +46,22 @@ impl Vector3 {
         self / self.length()
     }
 
-    pub fn random_unit() -> Self {
-        loop {
-            let mut rand = rand::thread_rng();
+    /// 和单位向量 self 互相垂直的两个单位向量，三个向量组成一组正交基
+    pub fn basis(self) -> (Vector3, Vector3) {
+        let axis = match self.x.abs() > 0.9 {
+            true => Vector3::new(0.0, 1.0, 0.0),
+            false => Vector3::new(1.0, 0.0, 0.0),
+        };
+        let u = self.cross(axis).unit();
+        (u, self.cross(u))
+    }
 
+    pub fn random_unit(sampler: &mut Sampler) -> Self {
+        loop {
             let vector3 = Vector3 {
-                x: rand.gen_range(-1.0..1.0),
-                y: rand.gen_range(-1.0..1.0),
-                z: rand.gen_range(-1.0..1.0),
+                x: sampler.gen_range(-1.0..1.0),
+                y: sampler.gen_range(-1.0..1.0),
+                z: sampler.gen_range(-1.0..1.0),
             };
 
             if vector3.length() < 1.0 {
@@ -65,22 +70,24 @@ impl Vector3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vector3 {
-        let mut rand = rand::thread_rng();
+    pub fn random_in_unit_disk(sampler: &mut Sampler) -> Vector3 {
         loop {
-            let p = Vector3::new(rand.gen_range(-1.0..1.0), rand.gen_range(-1.0..1.0), 0.0);
+            let p = Vector3::new(
+                sampler.gen_range(-1.0..1.0),
+                sampler.gen_range(-1.0..1.0),
+                0.0,
+            );
             if p.length() < 1.0 {
                 return p;
             }
         }
     }
 
-    pub fn random(range: Range<f64>) -> Vector3 {
-        let mut rng = rand::thread_rng();
+    pub fn random(range: Range<f64>, sampler: &mut Sampler) -> Vector3 {
         Vector3 {
-            x: rng.gen_range(range.clone()),
-            y: rng.gen_range(range.clone()),
-            z: rng.gen_range(range),
+            x: sampler.gen_range(range.clone()),
+            y: sampler.gen_range(range.clone()),
+            z: sampler.gen_range(range),
         }
     }
 
@@ -169,3 +176,17 @@ impl Div<f64> for Vector3 {
         }
     }
 }
+
+// 按照轴的序号取分量，0、1、2 分别表示 x、y、z
+impl Index<usize> for Vector3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis out of range: {axis}"),
+        }
+    }
+}